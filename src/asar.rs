@@ -4,6 +4,15 @@
 //!
 //! This module requires the `asar` feature to be enabled.
 
+/// The `index.js` of an ASAR archive built by [Asar].
+pub(crate) fn index_js(archive: &[u8]) -> Option<Vec<u8>> {
+    let reader = asar::AsarReader::new(archive, None).ok()?;
+
+    reader
+        .read(std::path::Path::new("index.js"))
+        .map(|file| file.data().to_vec())
+}
+
 fn make_package_json(wm_class: &Option<String>) -> String {
     if let Some(wm_class) = wm_class {
         format!(r#"{{"main": "index.js", "name": "{wm_class}"}}"#)
@@ -20,8 +29,8 @@ fn make_package_json(wm_class: &Option<String>) -> String {
 /// use electron_hook::asar::Asar;
 /// use electron_hook::paths::{mod_artifact_dir, data_profile_dir};
 ///
-/// let entrypoint = mod_artifact_dir("vencord").join("patcher.js");
-/// let profile_dir = data_profile_dir("vencord");
+/// let entrypoint = mod_artifact_dir("vencord")?.join("patcher.js");
+/// let profile_dir = data_profile_dir("vencord")?;
 ///
 /// let asar = Asar::new()
 ///     .with_id("vencord-release")
@@ -65,6 +74,7 @@ pub struct Asar {
     /// | `MODLOADER_PROFILE_DIR`            | The path to the custom profile directory        | Optional                 |
     /// | `MODLOADER_WM_CLASS`               | The WM_CLASS of the Electron application.       | Optional                 |
     /// | `MODLOADER_FOLDER_NAME`            | the app-<version> folder name                   | Windows only             |
+    /// | `MODLOADER_INJECTION`              | `preload`, `node-options` or `inspector`        | Linux only               |
     ///
     /// For a basic implementation, you want to at least require your mod, e.g.:
     ///
@@ -88,10 +98,11 @@ pub struct Asar {
     ///
     /// ```rust
     /// use electron_hook::paths::mod_artifact_dir;
-    /// let entrypoint = mod_artifact_dir("vencord").join("patcher.js");
+    /// let entrypoint = mod_artifact_dir("vencord")?.join("patcher.js");
     /// // Linux: /home/CoolPerson/.cache/electron-hook/mods/vencord/patcher.js
     /// // Windows: C:/Users/CoolPerson/AppData/Local/electron-hook/mods/vencord/patcher.js
     /// // MacOS: TODO
+    /// # Ok::<(), electron_hook::Error>(())
     /// ```
     pub mod_entrypoint: String,
 
@@ -100,16 +111,19 @@ pub struct Asar {
     /// A profile is a unique instance of an application's data directory - meaning separate settings, cache, chromium instance, etc.
     /// You do not need to use this for basic installs, but if you want to run multiple instances of the same client with different mods or settings, you can use this.
     ///
+    /// On Linux, the [Launcher](crate::Launcher) passes this to the application as `--user-data-dir`, so no template code is needed to apply it.
+    ///
     /// Preferably, you should get the path using [electron_hook::paths::data_profile_dir]
     ///
     /// You can use it like so:
     ///
     /// ```rust
     /// use electron_hook::paths::data_profile_dir;
-    /// let profile_dir = data_profile_dir("moonlight");
+    /// let profile_dir = data_profile_dir("moonlight")?;
     /// // Linux: /home/CoolPerson/.local/share/electron-hook/profiles/moonlight
     /// // Windows: C:/Users/CoolPerson/AppData/Roaming/electron-hook/profiles/moonlight
     /// // MacOS: TODO
+    /// # Ok::<(), electron_hook::Error>(())
     /// ```
     pub profile_dir: Option<String>,
 }
@@ -121,8 +135,14 @@ impl Asar {
     }
 
     /// Get the path to the ASAR archive.
-    pub fn get_path(&self) -> Option<std::path::PathBuf> {
-        (!self.id.is_empty()).then(|| crate::paths::asar_cache_path(&self.id))
+    ///
+    /// Returns `Ok(None)` if no ID has been set yet.
+    pub fn get_path(&self) -> crate::Result<Option<std::path::PathBuf>> {
+        if self.id.is_empty() {
+            return Ok(None);
+        }
+
+        crate::paths::asar_cache_path(&self.id).map(Some)
     }
 
     /// Generate a random UUID for the ASAR archive to use.
@@ -177,27 +197,58 @@ impl Asar {
         self
     }
 
-    /// Create the ASAR file and write it to disk, returning the path to the ASAR file.
+    fn write_to(&self, writer: impl std::io::Write) -> Result<(), asar::Error> {
+        let mut asar = asar::AsarWriter::new();
+
+        asar.write_file("index.js", self.template.clone(), false)?;
+        asar.write_file("package.json", make_package_json(&self.wm_class), false)?;
+
+        asar.finalize(writer)?;
+
+        Ok(())
+    }
+
+    /// Build the ASAR archive in memory, without writing anything to disk.
     ///
-    /// See [Usage](crate::asar::Asar#usage) for how the path is generated.
-    pub fn create(&self) -> Result<std::path::PathBuf, String> {
-        use crate::paths::asar_cache_path;
+    /// Pass the result to [Launcher::with_asar_bytes](crate::Launcher::with_asar_bytes) to serve it to the
+    /// application from memory.
+    ///
+    /// ```rust
+    /// use electron_hook::asar::Asar;
+    ///
+    /// let bytes = Asar::new()
+    ///     .with_template("require(process.env.MODLOADER_MOD_ENTRYPOINT);")
+    ///     .build()?;
+    ///
+    /// assert!(!bytes.is_empty());
+    /// # Ok::<(), electron_hook::Error>(())
+    /// ```
+    pub fn build(&self) -> crate::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
 
-        let asar_path = asar_cache_path(&self.id);
+        self.write_to(&mut bytes)
+            .map_err(|e| crate::Error::AsarBuild(Box::new(e)))?;
 
-        let mut asar = asar::AsarWriter::new();
+        Ok(bytes)
+    }
 
-        asar.write_file("index.js", self.template.clone(), false)
-            .map_err(|e| format!("Failed to write index.js: {e}"))?;
+    /// Create the ASAR file and write it to disk, returning the path to the ASAR file.
+    ///
+    /// See [Usage](crate::asar::Asar#usage) for how the path is generated.
+    pub fn create(&self) -> crate::Result<std::path::PathBuf> {
+        use crate::{paths::asar_cache_path, Error};
 
-        asar.write_file("package.json", make_package_json(&self.wm_class), false)
-            .map_err(|e| format!("Failed to write package.json: {e}"))?;
+        let asar_path = asar_cache_path(&self.id)?;
 
-        let file = std::fs::File::create(&asar_path)
-            .map_err(|e| format!("Failed to create file at {}: {e}", asar_path.display()))?;
+        let file = std::fs::File::create(&asar_path).map_err(|e| Error::AsarWrite {
+            path: asar_path.clone(),
+            source: Box::new(e),
+        })?;
 
-        asar.finalize(file)
-            .map_err(|e| format!("Failed to write asar to disk with error: {e}"))?;
+        self.write_to(file).map_err(|e| Error::AsarWrite {
+            path: asar_path.clone(),
+            source: Box::new(e),
+        })?;
 
         Ok(asar_path)
     }
@@ -0,0 +1,207 @@
+//! Error types
+
+use std::path::PathBuf;
+
+/// A specialized [Result](std::result::Result) type for electron-hook operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The error type for launching applications, creating ASAR archives and resolving paths.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The executable to launch does not exist.
+    ExecutableNotFound(PathBuf),
+
+    /// The application could not be started.
+    Spawn(std::io::Error),
+
+    /// Waiting for the application to exit failed.
+    Wait(std::io::Error),
+
+    /// The application could not be signalled to stop.
+    Kill(std::io::Error),
+
+    /// `MODLOADER_MOD_ENTRYPOINT` is not set.
+    ///
+    /// See [Asar::with_mod_entrypoint](crate::asar::Asar::with_mod_entrypoint)
+    MissingModEntrypoint,
+
+    /// The Flatpak app is not installed in either the user or the system installation.
+    FlatpakNotInstalled(String),
+
+    /// A path can't be made visible inside the Flatpak sandbox.
+    SandboxUnreachable(PathBuf),
+
+    /// The snap is not installed.
+    SnapNotInstalled(String),
+
+    /// A path can't be made visible to a strictly confined snap.
+    SnapConfinement {
+        /// The name of the snap.
+        name: String,
+        /// The path the snap can't read.
+        path: PathBuf,
+    },
+
+    /// An AppImage could not be extracted.
+    AppImageExtract {
+        /// The AppImage that was being extracted.
+        path: PathBuf,
+        /// The underlying error.
+        source: std::io::Error,
+    },
+
+    /// A required [Launcher](crate::Launcher) option was not provided.
+    MissingOption(&'static str),
+
+    /// A path could not be used, e.g. it has no parent directory or is not valid UTF-8.
+    InvalidPath(PathBuf),
+
+    /// The path of the current executable could not be determined.
+    CurrentExe(std::io::Error),
+
+    /// The ASAR archive could not be built or written to disk.
+    AsarWrite {
+        /// Where the archive was being written to.
+        path: PathBuf,
+        /// The underlying error.
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// The ASAR archive could not be built in memory.
+    AsarBuild(Box<dyn std::error::Error + Send + Sync>),
+
+    /// An in-memory ASAR archive could not be handed to the application.
+    AsarMemory(std::io::Error),
+
+    /// The user's cache directory could not be determined.
+    CacheDirUnavailable,
+
+    /// The user's data directory could not be determined.
+    DataDirUnavailable,
+
+    /// A log file could not be created.
+    Log {
+        /// The log file that was being created.
+        path: PathBuf,
+        /// The underlying error.
+        source: std::io::Error,
+    },
+
+    /// A directory could not be created.
+    CreateDir {
+        /// The directory that was being created.
+        path: PathBuf,
+        /// The underlying error.
+        source: std::io::Error,
+    },
+
+    /// The fuses of an Electron binary could not be read.
+    ReadFuses {
+        /// The binary that was being read.
+        path: PathBuf,
+        /// The underlying error.
+        source: std::io::Error,
+    },
+
+    /// A function could not be interposed.
+    ///
+    /// See [extension::interpose](crate::extension::interpose)
+    Interpose {
+        /// The function that was being interposed.
+        symbol: String,
+        /// Why it failed.
+        reason: String,
+    },
+
+    /// The ASAR template could not be injected through the application's inspector.
+    ///
+    /// See [Injection::Inspector](crate::Injection::Inspector)
+    Inspector(String),
+
+    /// The requested operation is not supported on this platform.
+    Unsupported(&'static str),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::ExecutableNotFound(path) => {
+                write!(f, "Executable not found: {}", path.display())
+            }
+            Error::Spawn(e) => write!(f, "Failed to launch instance: {e}"),
+            Error::Wait(e) => write!(f, "Failed to wait for instance: {e}"),
+            Error::Kill(e) => write!(f, "Failed to stop instance: {e}"),
+            Error::MissingModEntrypoint => write!(f, "MODLOADER_MOD_ENTRYPOINT not set"),
+            Error::FlatpakNotInstalled(id) => {
+                write!(
+                    f,
+                    "Flatpak {id} is not installed for the user or the system"
+                )
+            }
+            Error::SandboxUnreachable(path) => {
+                write!(
+                    f,
+                    "Path is not reachable from the sandbox: {}",
+                    path.display()
+                )
+            }
+            Error::SnapNotInstalled(name) => write!(f, "Snap {name} is not installed"),
+            Error::SnapConfinement { name, path } => {
+                write!(
+                    f,
+                    "Snap {name} can't access {} under its confinement",
+                    path.display()
+                )
+            }
+            Error::AppImageExtract { path, source } => {
+                write!(f, "Failed to extract AppImage {}: {source}", path.display())
+            }
+            Error::MissingOption(option) => write!(f, "Missing launcher option: {option}"),
+            Error::InvalidPath(path) => write!(f, "Invalid path: {}", path.display()),
+            Error::CurrentExe(e) => write!(f, "Failed to get current executable: {e}"),
+            Error::AsarWrite { path, source } => {
+                write!(f, "Failed to write asar to {}: {source}", path.display())
+            }
+            Error::AsarBuild(e) => write!(f, "Failed to build asar: {e}"),
+            Error::AsarMemory(e) => write!(f, "Failed to serve asar from memory: {e}"),
+            Error::CacheDirUnavailable => write!(f, "Failed to get cache directory"),
+            Error::DataDirUnavailable => write!(f, "Failed to get data directory"),
+            Error::Log { path, source } => {
+                write!(f, "Failed to create log file {}: {source}", path.display())
+            }
+            Error::CreateDir { path, source } => {
+                write!(f, "Failed to create directory {}: {source}", path.display())
+            }
+            Error::ReadFuses { path, source } => {
+                write!(f, "Failed to read fuses of {}: {source}", path.display())
+            }
+            Error::Interpose { symbol, reason } => {
+                write!(f, "Failed to interpose {symbol}: {reason}")
+            }
+            Error::Inspector(reason) => {
+                write!(f, "Failed to inject through the inspector: {reason}")
+            }
+            Error::Unsupported(what) => write!(f, "Unsupported on this platform: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Spawn(e)
+            | Error::Wait(e)
+            | Error::Kill(e)
+            | Error::CurrentExe(e)
+            | Error::AsarMemory(e) => Some(e),
+            Error::CreateDir { source, .. }
+            | Error::Log { source, .. }
+            | Error::AppImageExtract { source, .. }
+            | Error::ReadFuses { source, .. } => Some(source),
+            Error::AsarWrite { source, .. } => Some(source.as_ref()),
+            Error::AsarBuild(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Reading Electron fuses
+//!
+//! Fuses are feature toggles that are flipped in the Electron binary itself when an app is packaged, so
+//! they can't be changed at launch. They decide which injection strategies an app allows.
+//!
+//! ```rust
+//! use electron_hook::fuses::{Fuse, Fuses};
+//!
+//! // Not an Electron binary, so there are no fuses to read.
+//! let fuses = Fuses::read(std::path::Path::new("/bin/sh"))?;
+//! assert_eq!(fuses.get(Fuse::EnableNodeOptionsEnvironmentVariable), None);
+//! # Ok::<(), electron_hook::Error>(())
+//! ```
+
+use std::io::Read;
+use std::path::Path;
+
+use crate::{Error, Result};
+
+/// Marks the start of the fuse wire in the binary. It is followed by the wire version, the number of fuses,
+/// and one byte per fuse.
+const SENTINEL: &[u8] = b"dL7pKGdnNz796PbbjQWNKmHXBZaB9tsX";
+
+/// The fuses Electron knows about, by their position in the fuse wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fuse {
+    /// `ELECTRON_RUN_AS_NODE` is honoured.
+    RunAsNode = 0,
+    /// Cookies are encrypted on disk.
+    EnableCookieEncryption = 1,
+    /// `NODE_OPTIONS` is honoured.
+    EnableNodeOptionsEnvironmentVariable = 2,
+    /// `--inspect` and friends are honoured.
+    EnableNodeCliInspectArguments = 3,
+    /// `app.asar` is checked against the hash embedded in the binary.
+    EnableEmbeddedAsarIntegrityValidation = 4,
+    /// The app is only loaded from `app.asar`.
+    OnlyLoadAppFromAsar = 5,
+    /// The browser process uses its own V8 snapshot.
+    LoadBrowserProcessSpecificV8Snapshot = 6,
+    /// `file://` pages get extra privileges.
+    GrantFileProtocolExtraPrivileges = 7,
+}
+
+/// The fuse wire of an Electron binary.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fuses {
+    wire: Vec<u8>,
+}
+
+impl Fuses {
+    /// Read the fuses of the Electron binary at `executable`.
+    ///
+    /// Binaries without a fuse wire, like Electron before version 12 or anything that isn't Electron, have
+    /// no fuses.
+    pub fn read(executable: &Path) -> Result<Self> {
+        let read_error = |source| Error::ReadFuses {
+            path: executable.into(),
+            source,
+        };
+
+        let mut file = std::fs::File::open(executable).map_err(read_error)?;
+
+        // Electron binaries are large, so search them in chunks, keeping enough of the previous chunk to
+        // find a wire that is split between two.
+        let mut chunk = vec![0; 1 << 20];
+        let mut buffer = Vec::new();
+
+        loop {
+            let read = file.read(&mut chunk).map_err(read_error)?;
+
+            if read == 0 {
+                return Ok(Self::default());
+            }
+
+            buffer.extend_from_slice(&chunk[..read]);
+
+            if let Some(wire) = find_wire(&buffer) {
+                // Not all of the wire may have been read yet.
+                if let Some(wire) = wire {
+                    return Ok(Self { wire });
+                }
+
+                continue;
+            }
+
+            let keep = buffer.len().min(SENTINEL.len() + 2 + u8::MAX as usize);
+            buffer.drain(..buffer.len() - keep);
+        }
+    }
+
+    /// Whether `fuse` is enabled, or `None` if the binary doesn't have it.
+    pub fn get(&self, fuse: Fuse) -> Option<bool> {
+        match self.wire.get(fuse as usize)? {
+            b'1' => Some(true),
+            b'0' => Some(false),
+            // Removed fuses can't be enabled.
+            _ => Some(false),
+        }
+    }
+}
+
+/// Find the fuse wire in `buffer`. Returns `Some(None)` if the sentinel was found, but not the whole wire.
+fn find_wire(buffer: &[u8]) -> Option<Option<Vec<u8>>> {
+    let start = buffer
+        .windows(SENTINEL.len())
+        .position(|window| window == SENTINEL)?;
+
+    // Skip the version.
+    let header = &buffer[start + SENTINEL.len()..];
+
+    let Some(&len) = header.get(1) else {
+        return Some(None);
+    };
+
+    Some(header.get(2..2 + len as usize).map(<[u8]>::to_vec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fuse wire with `fuses`, after `padding` bytes of something else.
+    fn binary(padding: usize, fuses: &[u8]) -> Vec<u8> {
+        let mut binary = vec![b'x'; padding];
+        binary.extend_from_slice(SENTINEL);
+        binary.push(1);
+        binary.push(fuses.len() as u8);
+        binary.extend_from_slice(fuses);
+        binary.extend_from_slice(b"more binary");
+        binary
+    }
+
+    fn read_fuses(name: &str, binary: &[u8]) -> Fuses {
+        let path =
+            std::env::temp_dir().join(format!("electron-hook-fuses-{}-{name}", std::process::id()));
+        std::fs::write(&path, binary).unwrap();
+
+        let fuses = Fuses::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        fuses
+    }
+
+    #[test]
+    fn find_wire_reads_the_fuses() {
+        let binary = binary(100, b"01r1");
+        assert_eq!(find_wire(&binary), Some(Some(b"01r1".to_vec())));
+
+        // Only part of the wire.
+        assert_eq!(find_wire(&binary[..100 + SENTINEL.len()]), Some(None));
+        assert_eq!(find_wire(&binary[..100 + SENTINEL.len() + 2]), Some(None));
+        assert_eq!(find_wire(&binary[..100 + SENTINEL.len() + 5]), Some(None));
+
+        assert_eq!(find_wire(&binary[..100 + SENTINEL.len() - 1]), None);
+    }
+
+    #[test]
+    fn fuses_map_to_enabled() {
+        let fuses = read_fuses("values", &binary(100, b"10r"));
+
+        assert_eq!(fuses.get(Fuse::RunAsNode), Some(true));
+        assert_eq!(fuses.get(Fuse::EnableCookieEncryption), Some(false));
+        assert_eq!(
+            fuses.get(Fuse::EnableNodeOptionsEnvironmentVariable),
+            Some(false)
+        );
+        assert_eq!(fuses.get(Fuse::EnableNodeCliInspectArguments), None);
+    }
+
+    #[test]
+    fn wires_split_between_chunks_are_read() {
+        let chunk = 1 << 20;
+
+        // The sentinel split between chunks, then the wire split after the sentinel and in the fuses.
+        for padding in [
+            chunk - 10,
+            chunk - SENTINEL.len(),
+            chunk - SENTINEL.len() - 2,
+        ] {
+            let fuses = read_fuses(&format!("split-{padding}"), &binary(padding, b"0101"));
+
+            assert_eq!(fuses.get(Fuse::RunAsNode), Some(false));
+            assert_eq!(fuses.get(Fuse::EnableNodeCliInspectArguments), Some(true));
+        }
+
+        // In a later chunk, after some have been dropped.
+        let fuses = read_fuses("later", &binary(3 * chunk + 5, b"1"));
+        assert_eq!(fuses.get(Fuse::RunAsNode), Some(true));
+    }
+
+    #[test]
+    fn truncated_wires_have_no_fuses() {
+        let binary = binary(100, b"0101");
+
+        for len in [
+            100 + SENTINEL.len(),
+            100 + SENTINEL.len() + 2,
+            100 + SENTINEL.len() + 3,
+        ] {
+            let fuses = read_fuses(&format!("truncated-{len}"), &binary[..len]);
+            assert_eq!(fuses, Fuses::default());
+        }
+    }
+}
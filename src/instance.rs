@@ -0,0 +1,253 @@
+//! Handles to launched applications
+
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+
+use crate::{Error, Result};
+
+/// How often [Instance::terminate] checks whether the application has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A handle to a running application, returned by [Launcher::launch](crate::Launcher::launch).
+///
+/// The returned [ExitStatus] can be used to tell a crash apart from a clean exit:
+///
+/// ```rust,ignore
+/// use std::os::unix::process::ExitStatusExt;
+///
+/// let mut instance = launcher.launch()?;
+/// let status = instance.wait()?;
+///
+/// if let Some(signal) = status.signal() {
+///     eprintln!("Discord crashed with signal {signal}");
+/// }
+/// ```
+///
+/// Dropping an Instance does not stop the application.
+#[derive(Debug)]
+pub struct Instance {
+    inner: imp::Inner,
+}
+
+impl Instance {
+    #[cfg(unix)]
+    pub(crate) fn from_child(child: std::process::Child) -> Self {
+        Self {
+            inner: imp::Inner { child },
+        }
+    }
+
+    #[cfg(windows)]
+    pub(crate) fn from_handle(handle: winapi::um::winnt::HANDLE, pid: u32) -> Self {
+        Self {
+            inner: imp::Inner { handle, pid },
+        }
+    }
+
+    /// The process ID of the application.
+    ///
+    /// For Flatpak targets, this is the PID of the `flatpak run` process on the host.
+    pub fn pid(&self) -> u32 {
+        self.inner.pid()
+    }
+
+    /// Block until the application exits, returning its exit status.
+    pub fn wait(&mut self) -> Result<ExitStatus> {
+        self.inner.wait().map_err(Error::Wait)
+    }
+
+    /// Check whether the application has exited without blocking.
+    ///
+    /// Returns `Ok(None)` if it is still running.
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>> {
+        self.inner.try_wait().map_err(Error::Wait)
+    }
+
+    /// Forcefully stop the application.
+    ///
+    /// On Linux this sends `SIGKILL`.
+    pub fn kill(&mut self) -> Result<()> {
+        self.inner.kill().map_err(Error::Kill)
+    }
+
+    /// Ask the application to exit, and forcefully stop it if it is still running after `grace`.
+    ///
+    /// On Linux this sends `SIGTERM`, followed by `SIGKILL`.
+    ///
+    /// Windows has no equivalent to `SIGTERM`, so the application is stopped immediately.
+    pub fn terminate(&mut self, grace: Duration) -> Result<ExitStatus> {
+        if let Some(status) = self.try_wait()? {
+            return Ok(status);
+        }
+
+        self.inner.terminate().map_err(Error::Kill)?;
+
+        let deadline = Instant::now() + grace;
+        while Instant::now() < deadline {
+            if let Some(status) = self.try_wait()? {
+                return Ok(status);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        // The process may have exited between our last check and now.
+        if let Some(status) = self.try_wait()? {
+            return Ok(status);
+        }
+
+        self.kill()?;
+        self.wait()
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::process::{Child, ExitStatus};
+
+    #[derive(Debug)]
+    pub(super) struct Inner {
+        pub(super) child: Child,
+    }
+
+    impl Inner {
+        pub(super) fn pid(&self) -> u32 {
+            self.child.id()
+        }
+
+        pub(super) fn wait(&mut self) -> std::io::Result<ExitStatus> {
+            self.child.wait()
+        }
+
+        pub(super) fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>> {
+            self.child.try_wait()
+        }
+
+        pub(super) fn kill(&mut self) -> std::io::Result<()> {
+            self.child.kill()
+        }
+
+        pub(super) fn terminate(&mut self) -> std::io::Result<()> {
+            if unsafe { libc::kill(self.child.id() as libc::pid_t, libc::SIGTERM) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::os::windows::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    use winapi::um::{
+        handleapi::CloseHandle,
+        processthreadsapi::{GetExitCodeProcess, TerminateProcess},
+        synchapi::WaitForSingleObject,
+        winbase::{INFINITE, WAIT_OBJECT_0},
+        winnt::HANDLE,
+    };
+
+    #[derive(Debug)]
+    pub(super) struct Inner {
+        pub(super) handle: HANDLE,
+        pub(super) pid: u32,
+    }
+
+    impl Inner {
+        pub(super) fn pid(&self) -> u32 {
+            self.pid
+        }
+
+        fn exit_status(&self) -> std::io::Result<ExitStatus> {
+            let mut code = 0;
+            if unsafe { GetExitCodeProcess(self.handle, &mut code) } == 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(ExitStatus::from_raw(code))
+        }
+
+        pub(super) fn wait(&mut self) -> std::io::Result<ExitStatus> {
+            if unsafe { WaitForSingleObject(self.handle, INFINITE) } != WAIT_OBJECT_0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            self.exit_status()
+        }
+
+        pub(super) fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>> {
+            match unsafe { WaitForSingleObject(self.handle, 0) } {
+                WAIT_OBJECT_0 => self.exit_status().map(Some),
+                _ => Ok(None),
+            }
+        }
+
+        pub(super) fn kill(&mut self) -> std::io::Result<()> {
+            if unsafe { TerminateProcess(self.handle, 1) } == 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        pub(super) fn terminate(&mut self) -> std::io::Result<()> {
+            self.kill()
+        }
+    }
+
+    impl Drop for Inner {
+        fn drop(&mut self) {
+            unsafe { CloseHandle(self.handle) };
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn terminate_stops_with_sigterm() {
+        let mut instance = Instance::from_child(Command::new("sleep").arg("30").spawn().unwrap());
+
+        assert!(instance.try_wait().unwrap().is_none());
+
+        let status = instance.terminate(Duration::from_secs(10)).unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGTERM));
+    }
+
+    #[test]
+    fn terminate_kills_after_the_grace_period() {
+        // Ignored signals stay ignored across exec, so `sleep` ignores SIGTERM too.
+        let mut child = Command::new("sh")
+            .args(["-c", "trap '' TERM; echo ready; exec sleep 30"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let mut ready = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut ready)
+            .unwrap();
+        assert_eq!(ready, "ready\n");
+
+        let mut instance = Instance::from_child(child);
+        let started = Instant::now();
+
+        let status = instance.terminate(Duration::from_millis(300)).unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+        assert!(started.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[test]
+    fn terminate_returns_the_status_of_exited_apps() {
+        let mut instance = Instance::from_child(Command::new("true").spawn().unwrap());
+        instance.wait().unwrap();
+
+        let status = instance.terminate(Duration::from_secs(10)).unwrap();
+        assert_eq!(status.code(), Some(0));
+        assert_eq!(instance.try_wait().unwrap(), Some(status));
+    }
+}
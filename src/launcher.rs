@@ -0,0 +1,470 @@
+//! Builder for launching Electron applications
+
+use std::path::PathBuf;
+
+use crate::{redirect::Rule, Error, FlatpakTarget, Instance, LogRotation};
+
+/// The application that a [Launcher] will start.
+#[derive(Debug, Clone)]
+pub enum Target {
+    /// An Electron executable on disk.
+    ///
+    /// On Linux, this is the path to the executable. If the launcher itself runs inside a Flatpak, this is a
+    /// path on the host, and wrapper scripts are not followed since the launcher can't read them.
+    ///
+    /// On Windows, this is the path to the `app-<version>` executable inside the directory containing `Update.exe`.
+    Executable(PathBuf),
+    /// A Flatpak package. This is only available on Linux.
+    Flatpak(FlatpakTarget),
+    /// The name of a snap package, e.g. `discord`. This is only available on Linux.
+    ///
+    /// Strictly confined snaps can only read non-hidden files in the home directory, so the library, ASAR
+    /// and mod are copied into `~/snap/<name>/common/electron-hook` when needed. The profile directory is
+    /// never copied, so it must be somewhere the snap can write to.
+    Snap(String),
+    /// The path to an AppImage. This is only available on Linux.
+    ///
+    /// The AppImage mounts itself with FUSE, and the hooks find `resources/app.asar` inside the mount. If FUSE
+    /// is unavailable, it is extracted with `--appimage-extract` into the electron-hook cache instead.
+    AppImage(PathBuf),
+}
+
+/// What to do with the standard streams of the launched application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdioPolicy {
+    /// Share the launcher's stdin, stdout and stderr.
+    Inherit,
+    /// Discard all output and close stdin.
+    Null,
+    /// Write stdout and stderr to a new log file for every launch, and close stdin.
+    ///
+    /// Log files are named after the launch time, and kept per ASAR ID in [paths::log_dir](crate::paths::log_dir).
+    Log(LogRotation),
+}
+
+/// Which processes the hook library is preloaded into.
+///
+/// `LD_PRELOAD` is inherited by every process the application starts, so the hooks remove it, along with the
+/// `MODLOADER_*` variables, from the environment of programs outside the policy. Programs that start the
+/// application, like wrapper scripts or the AppImage runtime, always pass it on. This is only used on Linux.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PreloadPolicy {
+    /// Only the main (browser) process. Zygotes, renderers and every other child process start without it.
+    BrowserOnly,
+    /// The browser process and its Electron child processes, i.e. executables with a `resources` directory
+    /// next to them. Other programs the application starts, like `xdg-open`, start without it.
+    #[default]
+    Electron,
+    /// Every process the application starts.
+    All,
+}
+
+impl PreloadPolicy {
+    /// The value of `MODLOADER_PRELOAD_POLICY` for this policy.
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            PreloadPolicy::BrowserOnly => "browser",
+            PreloadPolicy::Electron => "electron",
+            PreloadPolicy::All => "all",
+        }
+    }
+}
+
+/// How the ASAR template is injected into the application.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Injection {
+    /// Preload the hook library, which redirects the app's `app.asar` to the generated ASAR.
+    #[default]
+    Preload,
+    /// Run the template before the app with `NODE_OPTIONS=--require`, without preloading anything.
+    ///
+    /// The template runs in the main process only, and the app then loads its own `app.asar` as usual, so
+    /// it must not load `MODLOADER_ORIGINAL_ASAR_RELATIVE` itself. `MODLOADER_INJECTION` is set to
+    /// `node-options` to tell it apart from [Injection::Preload], where it is `preload`.
+    ///
+    /// Electron ignores `NODE_OPTIONS` in packaged apps, apart from `--max-http-header-size` and
+    /// `--http-parser`, so this only works for apps run by an `electron` executable without an `app.asar`,
+    /// e.g. `electron path/to/app`.
+    ///
+    /// This falls back to [Injection::Preload] for packaged apps, if the app's fuses don't allow
+    /// `NODE_OPTIONS`, or if they can't be read, e.g. for Flatpaks, snaps and AppImages that aren't
+    /// extracted. It is only used on Linux.
+    NodeOptions,
+    /// Start the app with `--inspect-brk` on this address, and run the template in its main process over
+    /// the DevTools protocol before any of the app's own code, without preloading anything.
+    ///
+    /// This is for apps whose files can't be redirected, e.g. because they validate `app.asar` or are
+    /// statically linked. The template runs where the app first pauses, with `require` in scope, and the
+    /// inspector is closed as soon as the app resumes. If the template is not available, e.g. without the
+    /// `asar` feature, the mod entrypoint is required instead. Like [Injection::NodeOptions], the app
+    /// loads its own `app.asar`, and `MODLOADER_INJECTION` is set to `inspector`.
+    ///
+    /// The address should be a loopback one, as anything that can reach it can run code in the app until
+    /// the template has run. With port 0, the app picks a free port.
+    ///
+    /// ```rust
+    /// use electron_hook::Injection;
+    ///
+    /// let injection = Injection::Inspector(([127, 0, 0, 1], 0).into());
+    /// # let _ = injection;
+    /// ```
+    ///
+    /// This falls back to [Injection::Preload] if the app's fuses don't allow `--inspect`, or can't be read,
+    /// like [Injection::NodeOptions]. It is only used on Linux.
+    Inspector(std::net::SocketAddr),
+}
+
+/// A builder for launching an Electron application with electron-hook injected.
+///
+/// # Usage
+///
+/// ```rust,ignore
+/// use electron_hook::Launcher;
+///
+/// let instance = Launcher::executable("/opt/discord/Discord")
+///     .with_library("/path/to/libmy_launcher.so")
+///     .with_asar(asar_path.to_str().unwrap())
+///     .with_args(["--pass-arguments-here"])
+///     .with_env("MY_MOD_DEBUG", "1")
+///     .with_detach(true)
+///     .launch()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Launcher {
+    /// The application to launch.
+    pub target: Target,
+
+    /// The path to the electron-hook `.so` or `.dll`
+    pub library_path: String,
+
+    /// The path to the ASAR file to inject
+    pub asar_path: String,
+
+    /// An ASAR archive to inject from memory instead of [Launcher::asar_path], e.g. from [Asar::build](crate::asar::Asar::build).
+    ///
+    /// On Linux, this is handed to the application as an inherited memfd, and the hooks redirect `app.asar`
+    /// to `/proc/self/fd/<fd>`, so no file is written to disk and sandboxes don't need access to the
+    /// electron-hook cache. This is not supported on Windows.
+    pub asar_bytes: Option<Vec<u8>>,
+
+    /// Arguments to pass to the executable
+    pub args: Vec<String>,
+
+    /// Extra environment variables to set for the application.
+    ///
+    /// These are applied after the `MODLOADER_*` variables, so they can be used to override them.
+    ///
+    /// On Linux, setting `MODLOADER_TRACE` to a directory the app can write to logs every hooked call to
+    /// `trace-<pid>.log` in it.
+    pub env: Vec<(String, String)>,
+
+    /// The working directory of the application.
+    ///
+    /// Defaults to the directory containing the executable. Like the executable, this is a path on the host
+    /// if the launcher itself runs inside a Flatpak.
+    pub working_dir: Option<PathBuf>,
+
+    /// What to do with the application's stdin, stdout and stderr.
+    ///
+    /// Defaults to [StdioPolicy::Null] when detaching, and [StdioPolicy::Inherit] otherwise.
+    pub stdio: Option<StdioPolicy>,
+
+    /// An optional alternative profile directory, exposed to the mod as `MODLOADER_PROFILE_DIR`.
+    ///
+    /// On Linux, this is also passed to the application as `--user-data-dir`, so Chromium keeps its settings,
+    /// cache and storage there. If it is not set, the profile from [Asar::with_profile_dir](crate::asar::Asar::with_profile_dir) is used.
+    ///
+    /// See [Asar::profile_dir](crate::asar::Asar::profile_dir)
+    pub profile_dir: Option<String>,
+
+    /// Extra path redirections for the hooks, checked in order before the default `app.asar` ones.
+    ///
+    /// Targets must be visible to the app, e.g. inside a Flatpak sandbox. This is only used on Linux.
+    ///
+    /// See [redirect](crate::redirect)
+    pub redirects: Vec<Rule>,
+
+    /// Which processes the hook library is preloaded into. Defaults to [PreloadPolicy::Electron].
+    pub preload_policy: PreloadPolicy,
+
+    /// How the ASAR template is injected. Defaults to [Injection::Preload].
+    pub injection: Injection,
+
+    /// Whether to detach the application from the launcher.
+    ///
+    /// This does not affect whether [Launcher::launch] blocks. Use [Instance::wait] to wait for the application to exit.
+    ///
+    /// It is recommended to keep this enabled to prevent the process from dying when the parent process is closed.
+    /// On Windows, the application is always detached.
+    pub detach: bool,
+}
+
+impl Launcher {
+    /// Create a new Launcher for the given target.
+    pub fn new(target: Target) -> Self {
+        Self {
+            target,
+            library_path: String::new(),
+            asar_path: String::new(),
+            asar_bytes: None,
+            args: Vec::new(),
+            env: Vec::new(),
+            working_dir: None,
+            stdio: None,
+            profile_dir: None,
+            redirects: Vec::new(),
+            preload_policy: PreloadPolicy::default(),
+            injection: Injection::default(),
+            detach: true,
+        }
+    }
+
+    /// Create a new Launcher for an Electron executable.
+    ///
+    /// See [Target::Executable]
+    pub fn executable(executable: &str) -> Self {
+        Self::new(Target::Executable(PathBuf::from(executable)))
+    }
+
+    /// Create a new Launcher for a Flatpak package.
+    ///
+    /// This accepts either a [FlatpakID](crate::FlatpakID), or a [FlatpakTarget] for more options.
+    ///
+    /// See [Target::Flatpak]
+    pub fn flatpak(target: impl Into<FlatpakTarget>) -> Self {
+        Self::new(Target::Flatpak(target.into()))
+    }
+
+    /// Create a new Launcher for a snap package.
+    ///
+    /// See [Target::Snap]
+    pub fn snap(name: &str) -> Self {
+        Self::new(Target::Snap(name.to_string()))
+    }
+
+    /// Create a new Launcher for an AppImage.
+    ///
+    /// See [Target::AppImage]
+    pub fn appimage(appimage: &str) -> Self {
+        Self::new(Target::AppImage(PathBuf::from(appimage)))
+    }
+
+    /// Provide the path to the electron-hook library.
+    ///
+    /// See [Launcher::library_path]
+    pub fn with_library(mut self, library_path: &str) -> Self {
+        self.library_path = library_path.to_string();
+        self
+    }
+
+    /// Provide the path to the ASAR file to inject.
+    ///
+    /// See [Launcher::asar_path]
+    pub fn with_asar(mut self, asar_path: &str) -> Self {
+        self.asar_path = asar_path.to_string();
+        self
+    }
+
+    /// Provide an ASAR archive to inject from memory.
+    ///
+    /// See [Launcher::asar_bytes]
+    pub fn with_asar_bytes(mut self, asar_bytes: Vec<u8>) -> Self {
+        self.asar_bytes = Some(asar_bytes);
+        self
+    }
+
+    /// Add a single argument to pass to the executable.
+    pub fn with_arg(mut self, arg: &str) -> Self {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    /// Add multiple arguments to pass to the executable.
+    pub fn with_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set an extra environment variable for the application.
+    ///
+    /// See [Launcher::env]
+    pub fn with_env(mut self, key: &str, value: &str) -> Self {
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Provide the working directory for the application.
+    ///
+    /// See [Launcher::working_dir]
+    pub fn with_working_dir(mut self, working_dir: &str) -> Self {
+        self.working_dir = Some(PathBuf::from(working_dir));
+        self
+    }
+
+    /// Choose what to do with the application's standard streams.
+    ///
+    /// See [Launcher::stdio]
+    pub fn with_stdio(mut self, stdio: StdioPolicy) -> Self {
+        self.stdio = Some(stdio);
+        self
+    }
+
+    /// Provide the profile directory for the application.
+    ///
+    /// See [Launcher::profile_dir]
+    pub fn with_profile_dir(mut self, profile_dir: &str) -> Self {
+        self.profile_dir = Some(profile_dir.to_string());
+        self
+    }
+
+    /// Add a path redirection for the hooks.
+    ///
+    /// See [Launcher::redirects]
+    pub fn with_redirect(mut self, rule: Rule) -> Self {
+        self.redirects.push(rule);
+        self
+    }
+
+    /// Choose which processes the hook library is preloaded into.
+    ///
+    /// See [Launcher::preload_policy]
+    pub fn with_preload_policy(mut self, preload_policy: PreloadPolicy) -> Self {
+        self.preload_policy = preload_policy;
+        self
+    }
+
+    /// Choose how the ASAR template is injected.
+    ///
+    /// See [Launcher::injection]
+    pub fn with_injection(mut self, injection: Injection) -> Self {
+        self.injection = injection;
+        self
+    }
+
+    /// Choose whether to detach the application from the launcher.
+    ///
+    /// See [Launcher::detach]
+    pub fn with_detach(mut self, detach: bool) -> Self {
+        self.detach = detach;
+        self
+    }
+
+    /// The ID of the injected ASAR, taken from its file name, or from [Asar::with_id](crate::asar::Asar::with_id)
+    /// for an in-memory ASAR.
+    pub(crate) fn asar_id(&self) -> String {
+        if self.asar_bytes.is_some() {
+            return std::env::var("MODLOADER_ASAR_ID").unwrap_or_default();
+        }
+
+        std::path::Path::new(&self.asar_path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// The profile directory to use, falling back to the one set by [Asar::with_profile_dir](crate::asar::Asar::with_profile_dir).
+    pub(crate) fn profile_dir(&self) -> Option<String> {
+        self.profile_dir
+            .clone()
+            .or_else(|| std::env::var("MODLOADER_PROFILE_DIR").ok())
+            .filter(|profile_dir| !profile_dir.is_empty())
+    }
+
+    /// The stdio policy to use, taking [Launcher::detach] into account.
+    pub(crate) fn stdio_policy(&self) -> StdioPolicy {
+        self.stdio.unwrap_or(if self.detach {
+            StdioPolicy::Null
+        } else {
+            StdioPolicy::Inherit
+        })
+    }
+
+    /// The first option that is set, but only implemented on Linux.
+    #[cfg(not(target_os = "linux"))]
+    fn linux_only_option(&self) -> Option<&'static str> {
+        if self.asar_bytes.is_some() {
+            return Some("in-memory ASARs");
+        }
+
+        if self
+            .stdio
+            .is_some_and(|stdio| stdio != StdioPolicy::Inherit)
+        {
+            return Some("stdio policies other than inheriting");
+        }
+
+        if !self.redirects.is_empty() {
+            return Some("redirects");
+        }
+
+        if self.preload_policy != PreloadPolicy::default() {
+            return Some("preload policies");
+        }
+
+        if self.injection != Injection::Preload {
+            return Some("injection methods other than preloading");
+        }
+
+        None
+    }
+
+    /// Launch the application, returning a handle to the running instance.
+    ///
+    /// This does not block. See [Instance::wait] to wait for the application to exit.
+    ///
+    /// On Linux, if this process is itself running inside a Flatpak, the application is started on the host
+    /// with `flatpak-spawn --host`. This needs the `--talk-name=org.freedesktop.Flatpak` permission. Paths
+    /// are translated to where the host sees them, and [Instance] then refers to the `flatpak-spawn`
+    /// process, which forwards signals to the application.
+    ///
+    /// On other platforms, setting an option that is only implemented on Linux, like [Launcher::redirects]
+    /// or [Launcher::injection], returns [Error::Unsupported] rather than being ignored.
+    pub fn launch(&self) -> crate::Result<Instance> {
+        if self.library_path.is_empty() {
+            return Err(Error::MissingOption("library_path"));
+        }
+
+        if self.asar_path.is_empty() && self.asar_bytes.is_none() {
+            return Err(Error::MissingOption("asar_path"));
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        if let Some(option) = self.linux_only_option() {
+            return Err(Error::Unsupported(option));
+        }
+
+        match &self.target {
+            #[cfg(target_os = "linux")]
+            Target::Executable(executable) => crate::linux::launch(self, executable),
+
+            #[cfg(target_os = "linux")]
+            Target::Flatpak(flatpak) => crate::linux::launch_flatpak(self, flatpak),
+
+            #[cfg(target_os = "linux")]
+            Target::Snap(name) => crate::linux::launch_snap(self, name),
+
+            #[cfg(target_os = "linux")]
+            Target::AppImage(appimage) => crate::linux::launch_appimage(self, appimage),
+
+            // No need for detach on Windows, as the process already detaches itself.
+            #[cfg(target_os = "windows")]
+            Target::Executable(executable) => crate::windows::launch(self, executable),
+
+            #[cfg(not(target_os = "linux"))]
+            Target::Flatpak(_) => Err(Error::Unsupported("Flatpak")),
+
+            #[cfg(not(target_os = "linux"))]
+            Target::Snap(_) => Err(Error::Unsupported("snap")),
+
+            #[cfg(not(target_os = "linux"))]
+            Target::AppImage(_) => Err(Error::Unsupported("AppImage")),
+
+            #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+            Target::Executable(_) => Err(Error::Unsupported("launching executables")),
+        }
+    }
+}
@@ -17,7 +17,7 @@
 //! ```rust,ignore
 //! use electron_hook::asar::Asar;
 //!
-//! let mod_dir = mod_artifact_dir("moonlight");
+//! let mod_dir = mod_artifact_dir("moonlight")?;
 //!
 //! let _download_url = "https://github.com/moonlight-mod/moonlight/releases/latest/download/dist.tar.gz";
 //! // extract and save `_download_url` into `mod_dir`
@@ -35,26 +35,41 @@
 //!     .with_id("moonlight")
 //!     .with_template(template)
 //!     .with_mod_entrypoint(mod_dir)
-//!     .create()
-//!     .unwrap();
+//!     .create()?;
 //!
-//! electron_hook::launch(
-//!     "/path/to/executable/Discord",
-//!     asar.path().to_str().unwrap(),
-//!     vec!["--pass-arguments-here"],
-//!     None, // Optional profile directory
-//!     true, // Detach the process
-//! );
+//! electron_hook::Launcher::executable("/path/to/executable/Discord")
+//!     .with_library("/path/to/libmy_launcher.so")
+//!     .with_asar(asar.to_str().unwrap())
+//!     .with_args(["--pass-arguments-here"])
+//!     .with_detach(true) // Detach the process
+//!     .launch()?;
 //! ```
 
 #[cfg(any(doc, feature = "asar"))]
 pub mod asar;
+pub mod fuses;
 pub mod paths;
+pub mod redirect;
+
+mod error;
+pub use error::{Error, Result};
+
+mod instance;
+pub use instance::Instance;
+
+mod launcher;
+pub use launcher::{Injection, Launcher, PreloadPolicy, StdioPolicy, Target};
+
+mod logs;
+pub use logs::LogRotation;
 
 // For Linux
 #[cfg(target_os = "linux")]
 mod linux;
 
+#[cfg(target_os = "linux")]
+pub use linux::{discovery, extension, sandbox};
+
 // For Windows
 // TODO: Re-implement Windows support.
 #[cfg(target_os = "windows")]
@@ -62,66 +77,8 @@ mod windows;
 
 // TODO: For MacOS
 
-/// Launches an Electron executable with the provided information.
-///
-/// `id` on Linux: the path to the executable.
-///
-/// `id` on Windows: the path to the directory containing `Update.exe`.
-///
-/// `library_path`: The path to the electron-hook `.so` or `.dll`
-///
-/// `asar_path`: The path to the ASAR file to inject
-///
-/// `args`: Arguments to pass to the executable
-///
-/// `detach`: It is recommended to set `detach` to true to prevent the process from dying when the parent process is closed.
-#[allow(unused_variables)]
-pub fn launch(
-    executable: &str,
-    library_path: &str,
-    asar_path: &str,
-    args: Vec<String>,
-    detach: bool,
-) -> Result<Option<u32>, String> {
-    #[cfg(target_os = "linux")]
-    {
-        linux::launch(executable, library_path, asar_path, args, detach)
-    }
-
-    #[cfg(target_os = "windows")]
-    {
-        // No need for detach on Windows, as the process already detaches itself.
-        windows::launch(executable, library_path, asar_path, args)
-    }
-}
-
-/// Launches an Electron executable through Flatpak with the provided information.
-///
-/// This is only available on Linux.
-///
-/// TODO: This only supports global packages. Are --user flatpak packages handled differently?
-///
-/// `id`: The ID of the flatpak package.
-///
-/// `library_path`: The path to the electron-hook `.so` or `.dll`
-///
-/// `asar_path`: The path to the ASAR file to inject
-///
-/// `args`: Arguments to pass to the executable
-///
-/// `detach`: It is recommended to set `detach` to true to prevent the process from dying when the parent process is closed.
-#[cfg(any(doc, target_os = "linux"))]
-pub fn launch_flatpak(
-    id: &FlatpakID,
-    library_path: &str,
-    asar_path: &str,
-    args: Vec<String>,
-    detach: bool,
-) -> Result<Option<u32>, String> {
-    linux::launch_flatpak(id, library_path, asar_path, args, detach)
-}
-
 /// The ID of a Flatpak package.
+#[derive(Debug, Clone)]
 pub enum FlatpakID {
     /// A User install of a flatpak package. Will be run with `--user`
     User(String),
@@ -129,6 +86,34 @@ pub enum FlatpakID {
     System(String),
 }
 
+impl FlatpakID {
+    /// Find out whether a Flatpak app is a user or a system install.
+    ///
+    /// If it is installed in both, the user install is preferred, like `flatpak run` does. A branch or
+    /// architecture in `app_id`, like `com.discordapp.Discord//beta`, is left out of the returned ID. Use
+    /// [FlatpakTarget::resolve] to keep it.
+    ///
+    /// This is only available on Linux.
+    ///
+    /// ```rust,no_run
+    /// use electron_hook::FlatpakID;
+    ///
+    /// let id = FlatpakID::resolve("com.discordapp.Discord")?;
+    /// # Ok::<(), electron_hook::Error>(())
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn resolve(app_id: &str) -> Result<Self> {
+        linux::flatpak::resolve(app_id)
+    }
+
+    /// The app ID, e.g. `com.discordapp.Discord`
+    pub fn id(&self) -> &str {
+        match self {
+            FlatpakID::User(id) | FlatpakID::System(id) => id,
+        }
+    }
+}
+
 impl std::fmt::Display for FlatpakID {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -137,3 +122,197 @@ impl std::fmt::Display for FlatpakID {
         }
     }
 }
+
+/// An extra sandbox permission to give a Flatpak app when launching it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlatpakPermission {
+    /// Passed as `--filesystem=`, e.g. `xdg-download:ro`
+    Filesystem(String),
+    /// Passed as `--socket=`, e.g. `wayland`
+    Socket(String),
+    /// Passed as `--env=KEY=VALUE`
+    Env(String, String),
+}
+
+impl FlatpakPermission {
+    /// The `flatpak run` argument for this permission.
+    pub fn to_arg(&self) -> String {
+        match self {
+            FlatpakPermission::Filesystem(filesystem) => format!("--filesystem={filesystem}"),
+            FlatpakPermission::Socket(socket) => format!("--socket={socket}"),
+            FlatpakPermission::Env(key, value) => format!("--env={key}={value}"),
+        }
+    }
+}
+
+/// A Flatpak app to launch, along with options for `flatpak run`.
+///
+/// ```rust
+/// use electron_hook::{FlatpakID, FlatpakPermission, FlatpakTarget};
+///
+/// let target = FlatpakTarget::new(FlatpakID::User("com.discordapp.DiscordCanary".into()))
+///     .with_branch("beta")
+///     .with_permission(FlatpakPermission::Socket("wayland".into()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FlatpakTarget {
+    /// The app to launch.
+    pub id: FlatpakID,
+    /// The branch to launch, e.g. `beta`. Passed as `--branch=`
+    pub branch: Option<String>,
+    /// The architecture to launch, e.g. `aarch64`. Passed as `--arch=`
+    pub arch: Option<String>,
+    /// The command to run instead of the app's default. Passed as `--command=`
+    pub command: Option<String>,
+    /// Extra sandbox permissions for the app.
+    pub permissions: Vec<FlatpakPermission>,
+}
+
+impl FlatpakTarget {
+    /// Create a new FlatpakTarget with the default command.
+    ///
+    /// The branch and architecture default to the ones in the ID if it is a ref like `app.id//beta` or
+    /// `app.id/aarch64/beta`, which are then left out of [FlatpakTarget::id].
+    pub fn new(id: FlatpakID) -> Self {
+        let (app_id, arch, branch) = split_ref(id.id());
+        let (arch, branch) = (arch.map(str::to_string), branch.map(str::to_string));
+
+        let id = match &id {
+            FlatpakID::User(_) => FlatpakID::User(app_id.to_string()),
+            FlatpakID::System(_) => FlatpakID::System(app_id.to_string()),
+        };
+
+        Self {
+            id,
+            branch,
+            arch,
+            command: None,
+            permissions: Vec::new(),
+        }
+    }
+
+    /// Find out whether a Flatpak app is a user or a system install, keeping the branch and architecture of
+    /// refs like `com.discordapp.Discord//beta`.
+    ///
+    /// This is only available on Linux.
+    ///
+    /// ```rust,no_run
+    /// use electron_hook::FlatpakTarget;
+    ///
+    /// let target = FlatpakTarget::resolve("com.discordapp.DiscordCanary//beta")?;
+    /// assert_eq!(target.branch.as_deref(), Some("beta"));
+    /// # Ok::<(), electron_hook::Error>(())
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn resolve(app_ref: &str) -> Result<Self> {
+        let (_, arch, branch) = split_ref(app_ref);
+
+        let mut target = Self::new(FlatpakID::resolve(app_ref)?);
+        target.arch = arch.map(str::to_string);
+        target.branch = branch.map(str::to_string);
+
+        Ok(target)
+    }
+
+    /// Provide the branch to launch.
+    ///
+    /// See [FlatpakTarget::branch]
+    pub fn with_branch(mut self, branch: &str) -> Self {
+        self.branch = Some(branch.to_string());
+        self
+    }
+
+    /// Provide the architecture to launch.
+    ///
+    /// See [FlatpakTarget::arch]
+    pub fn with_arch(mut self, arch: &str) -> Self {
+        self.arch = Some(arch.to_string());
+        self
+    }
+
+    /// Provide the command to run.
+    ///
+    /// See [FlatpakTarget::command]
+    pub fn with_command(mut self, command: &str) -> Self {
+        self.command = Some(command.to_string());
+        self
+    }
+
+    /// Add an extra sandbox permission.
+    ///
+    /// See [FlatpakTarget::permissions]
+    pub fn with_permission(mut self, permission: FlatpakPermission) -> Self {
+        self.permissions.push(permission);
+        self
+    }
+}
+
+impl From<FlatpakID> for FlatpakTarget {
+    fn from(id: FlatpakID) -> Self {
+        Self::new(id)
+    }
+}
+
+/// Split a Flatpak ref like `app.id//branch` or `app/app.id/arch/branch` into the app ID, architecture and
+/// branch, the way `flatpak run` reads it.
+pub(crate) fn split_ref(app_ref: &str) -> (&str, Option<&str>, Option<&str>) {
+    let app_ref = app_ref.strip_prefix("app/").unwrap_or(app_ref);
+
+    let mut parts = app_ref.splitn(3, '/');
+    let id = parts.next().unwrap_or_default();
+    let arch = parts.next().filter(|arch| !arch.is_empty());
+    let branch = parts.next().filter(|branch| !branch.is_empty());
+
+    (id, arch, branch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_ref_reads_branch_and_arch() {
+        let cases = [
+            (
+                "com.discordapp.Discord",
+                ("com.discordapp.Discord", None, None),
+            ),
+            (
+                "com.discordapp.Discord//beta",
+                ("com.discordapp.Discord", None, Some("beta")),
+            ),
+            (
+                "com.discordapp.Discord/aarch64/beta",
+                ("com.discordapp.Discord", Some("aarch64"), Some("beta")),
+            ),
+            (
+                "com.discordapp.Discord/x86_64",
+                ("com.discordapp.Discord", Some("x86_64"), None),
+            ),
+            (
+                "app/com.discordapp.Discord/x86_64/stable",
+                ("com.discordapp.Discord", Some("x86_64"), Some("stable")),
+            ),
+        ];
+
+        for (app_ref, expected) in cases {
+            assert_eq!(split_ref(app_ref), expected, "{app_ref}");
+        }
+    }
+
+    #[test]
+    fn flatpak_target_takes_branch_from_the_id() {
+        let target = FlatpakTarget::new(FlatpakID::System("com.discordapp.Discord//beta".into()));
+
+        assert!(matches!(&target.id, FlatpakID::System(id) if id == "com.discordapp.Discord"));
+        assert_eq!(target.branch.as_deref(), Some("beta"));
+        assert_eq!(target.arch, None);
+
+        // An explicit branch still wins.
+        let target = FlatpakTarget::from(FlatpakID::User("com.discordapp.Discord//beta".into()))
+            .with_branch("stable");
+
+        assert_eq!(target.id.id(), "com.discordapp.Discord");
+        assert_eq!(target.branch.as_deref(), Some("stable"));
+    }
+}
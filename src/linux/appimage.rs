@@ -0,0 +1,204 @@
+use std::path::{Path, PathBuf};
+
+use crate::{Error, Result};
+
+use super::discovery::which;
+
+/// Whether AppImages can mount themselves, which needs `/dev/fuse` and `fusermount`. Otherwise they are
+/// [extracted](extract).
+pub(crate) fn fuse_available() -> bool {
+    fuse_available_with(Path::new("/dev/fuse"), which)
+}
+
+/// Like [fuse_available], with the FUSE device at `dev_fuse` and programs found with `which`.
+fn fuse_available_with(dev_fuse: &Path, which: impl Fn(&str) -> Option<PathBuf>) -> bool {
+    dev_fuse.exists() && (which("fusermount").is_some() || which("fusermount3").is_some())
+}
+
+/// The name of the directory `appimage` is extracted to, from its name, size and modification time.
+fn extraction_name(appimage: &Path, metadata: &std::fs::Metadata) -> Result<String> {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .unwrap_or_default()
+        .as_secs();
+
+    let name = appimage
+        .file_stem()
+        .ok_or_else(|| Error::InvalidPath(appimage.into()))?
+        .to_string_lossy();
+
+    Ok(format!("{name}-{}-{modified}", metadata.len()))
+}
+
+/// Extract `appimage` into the electron-hook cache, returning the extracted directory.
+///
+/// Extractions are keyed by the AppImage's name, size and modification time, so updating the AppImage
+/// extracts it again, and launching the same one reuses the previous extraction.
+pub(crate) fn extract(appimage: &Path) -> Result<PathBuf> {
+    extract_into(appimage, &crate::paths::appimage_extract_dir()?)
+}
+
+/// Like [extract], into `extract_dir`.
+fn extract_into(appimage: &Path, extract_dir: &Path) -> Result<PathBuf> {
+    let extract_error = |source| Error::AppImageExtract {
+        path: appimage.into(),
+        source,
+    };
+
+    let metadata = std::fs::metadata(appimage).map_err(extract_error)?;
+    let name = extraction_name(appimage, &metadata)?;
+    let app_dir = extract_dir.join(&name);
+
+    if app_dir.join("AppRun").exists() {
+        return Ok(app_dir);
+    }
+
+    // `--appimage-extract` always extracts to `./squashfs-root`, so extract next to the final location
+    // and move it into place once it's complete.
+    let partial_dir = extract_dir.join(format!("{name}.partial-{}", std::process::id()));
+    crate::paths::ensure_dir(partial_dir.clone())?;
+
+    let status = std::process::Command::new(appimage)
+        .arg("--appimage-extract")
+        .current_dir(&partial_dir)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .status()
+        .map_err(extract_error)?;
+
+    let result = if status.success() {
+        std::fs::rename(partial_dir.join("squashfs-root"), &app_dir).map_err(extract_error)
+    } else {
+        Err(extract_error(std::io::Error::other(format!(
+            "--appimage-extract failed with {status}"
+        ))))
+    };
+
+    let _ = std::fs::remove_dir_all(&partial_dir);
+
+    result.map(|_| app_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::PermissionsExt;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "electron-hook-appimage-{}-{name}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A fake AppImage that logs every `--appimage-extract` to `extractions` next to it.
+    fn fake_appimage(dir: &Path, script: &str) -> PathBuf {
+        let appimage = dir.join("Discord-0.0.50.AppImage");
+        std::fs::write(&appimage, format!("#!/bin/sh\n{script}\n")).unwrap();
+        std::fs::set_permissions(&appimage, std::fs::Permissions::from_mode(0o755)).unwrap();
+        appimage
+    }
+
+    const EXTRACT: &str = "[ \"$1\" = --appimage-extract ] || exit 2\n\
+        echo \"$PWD\" >> \"$(dirname \"$0\")/extractions\"\n\
+        mkdir -p squashfs-root/resources && printf '#!/bin/sh\\n' > squashfs-root/AppRun";
+
+    #[test]
+    fn fuse_available_needs_the_device_and_fusermount() {
+        let dir = temp_dir("fuse");
+        let dev_fuse = dir.join("fuse");
+        let found = |name: &str| Some(PathBuf::from("/usr/bin").join(name));
+
+        assert!(!fuse_available_with(&dev_fuse, found));
+
+        std::fs::write(&dev_fuse, b"").unwrap();
+        assert!(fuse_available_with(&dev_fuse, found));
+        assert!(!fuse_available_with(&dev_fuse, |_| None));
+        assert!(fuse_available_with(&dev_fuse, |name| {
+            (name == "fusermount3").then(|| PathBuf::from("/usr/bin/fusermount3"))
+        }));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn extraction_name_changes_with_the_appimage() {
+        let dir = temp_dir("name");
+        let appimage = fake_appimage(&dir, "exit 0");
+
+        let name = |appimage: &Path| {
+            extraction_name(appimage, &std::fs::metadata(appimage).unwrap()).unwrap()
+        };
+
+        let file = std::fs::File::options()
+            .write(true)
+            .open(&appimage)
+            .unwrap();
+        file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1700000000))
+            .unwrap();
+        let len = file.metadata().unwrap().len();
+
+        assert_eq!(name(&appimage), format!("Discord-0.0.50-{len}-1700000000"));
+
+        file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1700000001))
+            .unwrap();
+        assert_eq!(name(&appimage), format!("Discord-0.0.50-{len}-1700000001"));
+
+        file.set_len(len + 1).unwrap();
+        file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1700000001))
+            .unwrap();
+        assert_eq!(
+            name(&appimage),
+            format!("Discord-0.0.50-{}-1700000001", len + 1)
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn extract_reuses_previous_extractions() {
+        let dir = temp_dir("extract");
+        let extract_dir = dir.join("extracted");
+        std::fs::create_dir_all(&extract_dir).unwrap();
+        let appimage = fake_appimage(&dir, EXTRACT);
+
+        let app_dir = extract_into(&appimage, &extract_dir).unwrap();
+        assert!(app_dir.join("AppRun").is_file());
+        assert!(app_dir.join("resources").is_dir());
+        assert_eq!(extract_into(&appimage, &extract_dir).unwrap(), app_dir);
+
+        // Only the finished extraction is left, and it was only extracted once.
+        let extractions = std::fs::read_to_string(dir.join("extractions")).unwrap();
+        assert_eq!(extractions.lines().count(), 1);
+        assert_eq!(std::fs::read_dir(&extract_dir).unwrap().count(), 1);
+
+        // An update is extracted again.
+        std::fs::write(&appimage, format!("#!/bin/sh\n{EXTRACT}\n# 0.0.51\n")).unwrap();
+        let updated_dir = extract_into(&appimage, &extract_dir).unwrap();
+        assert_ne!(updated_dir, app_dir);
+        assert!(updated_dir.join("AppRun").is_file());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn extract_cleans_up_after_failures() {
+        let dir = temp_dir("fail");
+        let extract_dir = dir.join("extracted");
+        std::fs::create_dir_all(&extract_dir).unwrap();
+        let appimage = fake_appimage(&dir, "mkdir -p squashfs-root; exit 1");
+
+        assert!(matches!(
+            extract_into(&appimage, &extract_dir),
+            Err(Error::AppImageExtract { .. })
+        ));
+        assert_eq!(std::fs::read_dir(&extract_dir).unwrap().count(), 0);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
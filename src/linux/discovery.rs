@@ -0,0 +1,440 @@
+//! Discovery of installed Electron applications
+//!
+//! Scans the common install locations on Linux for Electron apps, so callers don't need to know the
+//! executable path or Flatpak ID in advance.
+//!
+//! This module is only available on Linux.
+//!
+//! ```rust,no_run
+//! for candidate in electron_hook::discovery::discover() {
+//!     println!("{} -> {}", candidate.name, candidate.resources_dir.display());
+//! }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use crate::FlatpakID;
+
+/// How many directories deep to look for a `resources` directory below each search root.
+const MAX_DEPTH: usize = 3;
+
+/// Files that Electron ships next to its executable. Any one of them marks a directory as an Electron app.
+const ELECTRON_MARKERS: &[&str] = &[
+    "resources.pak",
+    "chrome_100_percent.pak",
+    "v8_context_snapshot.bin",
+    "libffmpeg.so",
+];
+
+/// Executables that Electron ships alongside the app, which are never the app itself.
+const HELPER_EXECUTABLES: &[&str] = &[
+    "chrome-sandbox",
+    "chrome_crashpad_handler",
+    "crashpad_handler",
+];
+
+/// An Electron application found by [discover].
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    /// A human-readable name for the app, taken from its install directory.
+    pub name: String,
+
+    /// The path to the Electron executable, if one was found.
+    ///
+    /// For Flatpak apps, this is the path on the host, which is not what runs inside the sandbox.
+    pub executable: Option<PathBuf>,
+
+    /// The `resources` directory of the app.
+    pub resources_dir: PathBuf,
+
+    /// Whether `resources/app.asar` exists. If not, the app is probably loaded from `resources/app`.
+    pub has_app_asar: bool,
+
+    /// The suggested [FlatpakID] to launch the app with, if it was found in a Flatpak installation.
+    pub flatpak_id: Option<FlatpakID>,
+}
+
+/// Scan all common install locations for Electron applications.
+///
+/// This looks in:
+/// - `/opt`, `/usr/share`, `/usr/lib`, `/usr/lib64`, `/usr/local/share` and `~/.local/share`
+/// - The system and user Flatpak installations
+/// - The `Exec=` lines of `.desktop` files
+///
+/// Each app is only returned once, even if it is found in multiple places.
+pub fn discover() -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+
+    let mut roots = vec![
+        PathBuf::from("/opt"),
+        PathBuf::from("/usr/share"),
+        PathBuf::from("/usr/lib"),
+        PathBuf::from("/usr/lib64"),
+        PathBuf::from("/usr/local/share"),
+    ];
+    roots.extend(dirs::data_dir());
+
+    for root in &roots {
+        candidates.extend(scan_dir(root));
+    }
+
+    candidates.extend(scan_flatpak_installations());
+    candidates.extend(scan_desktop_entries());
+
+    let mut seen = std::collections::HashSet::new();
+    candidates.retain(|candidate| {
+        let key = candidate
+            .resources_dir
+            .canonicalize()
+            .unwrap_or_else(|_| candidate.resources_dir.clone());
+        seen.insert(key)
+    });
+
+    candidates
+}
+
+/// Recursively scan `root` for Electron applications.
+pub fn scan_dir(root: &Path) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    scan_dir_inner(root, MAX_DEPTH, &mut candidates);
+    candidates
+}
+
+fn scan_dir_inner(dir: &Path, depth: usize, candidates: &mut Vec<Candidate>) {
+    if let Some(candidate) = candidate_from_app_dir(dir) {
+        candidates.push(candidate);
+        return;
+    }
+
+    if depth == 0 {
+        return;
+    }
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        // Don't follow symlinked directories, to avoid loops and scanning the same app twice.
+        let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+        if !is_dir || entry.file_name() == "node_modules" {
+            continue;
+        }
+
+        scan_dir_inner(&entry.path(), depth - 1, candidates);
+    }
+}
+
+/// Check whether `app_dir` is the install directory of an Electron app, i.e. it contains `resources/`.
+pub(crate) fn candidate_from_app_dir(app_dir: &Path) -> Option<Candidate> {
+    let resources_dir = app_dir.join("resources");
+
+    if !resources_dir.is_dir() {
+        return None;
+    }
+
+    let has_app_asar = resources_dir.join("app.asar").is_file();
+
+    let is_electron = has_app_asar
+        || ELECTRON_MARKERS
+            .iter()
+            .any(|marker| app_dir.join(marker).is_file());
+
+    if !is_electron {
+        return None;
+    }
+
+    let name = app_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    Some(Candidate {
+        executable: find_executable(app_dir, &name),
+        name,
+        resources_dir,
+        has_app_asar,
+        flatpak_id: None,
+    })
+}
+
+/// Whether the file at `path` is an ELF binary.
+pub(crate) fn is_elf(path: &Path) -> bool {
+    use std::io::Read;
+
+    let mut magic = [0u8; 4];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|_| &magic == b"\x7fELF")
+}
+
+/// Find the Electron executable in `app_dir`, preferring one named after the directory.
+fn find_executable(app_dir: &Path, name: &str) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut executables = std::fs::read_dir(app_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+
+            !HELPER_EXECUTABLES.contains(&file_name.as_ref()) && !file_name.contains(".so")
+        })
+        .filter(|entry| {
+            entry.metadata().is_ok_and(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            })
+        })
+        .map(|entry| entry.path())
+        .filter(|path| is_elf(path))
+        .collect::<Vec<PathBuf>>();
+
+    executables.sort();
+
+    let preferred = executables.iter().position(|path| {
+        path.file_name()
+            .is_some_and(|file_name| file_name.to_string_lossy().eq_ignore_ascii_case(name))
+    });
+
+    match preferred {
+        Some(index) => Some(executables.swap_remove(index)),
+        None => executables.into_iter().next(),
+    }
+}
+
+/// Creates the [FlatpakID] for an app in a specific Flatpak installation.
+pub(crate) type FlatpakScope = fn(String) -> FlatpakID;
+
+/// The `app` directories of the user and system Flatpak installations, in the order Flatpak prefers them.
+pub(crate) fn flatpak_installations() -> Vec<(PathBuf, FlatpakScope)> {
+    let mut installations = Vec::new();
+
+    let user_dir = std::env::var_os("FLATPAK_USER_DIR")
+        .map(PathBuf::from)
+        .or_else(|| dirs::data_dir().map(|dir| dir.join("flatpak")));
+
+    if let Some(user_dir) = user_dir {
+        installations.push((user_dir.join("app"), FlatpakID::User as FlatpakScope));
+    }
+
+    let system_dir = std::env::var_os("FLATPAK_SYSTEM_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/var/lib/flatpak"));
+
+    installations.push((system_dir.join("app"), FlatpakID::System));
+
+    installations
+}
+
+fn scan_flatpak_installations() -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+
+    for (apps_dir, make_id) in flatpak_installations() {
+        let Ok(entries) = std::fs::read_dir(&apps_dir) else {
+            continue;
+        };
+
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let app_id = entry.file_name().to_string_lossy().into_owned();
+            let files_dir = entry.path().join("current/active/files");
+
+            candidates.extend(scan_dir(&files_dir).into_iter().map(|mut candidate| {
+                candidate.flatpak_id = Some(make_id(app_id.clone()));
+                candidate
+            }));
+        }
+    }
+
+    candidates
+}
+
+/// The directories that `.desktop` files are installed to.
+fn application_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![
+        PathBuf::from("/usr/share/applications"),
+        PathBuf::from("/usr/local/share/applications"),
+        PathBuf::from("/var/lib/flatpak/exports/share/applications"),
+    ];
+
+    if let Some(data_dir) = dirs::data_dir() {
+        dirs.push(data_dir.join("applications"));
+        dirs.push(data_dir.join("flatpak/exports/share/applications"));
+    }
+
+    dirs
+}
+
+/// Get the program from the `Exec=` line of a `.desktop` file.
+///
+/// Only the `[Desktop Entry]` group counts, since actions have their own `Exec=` lines, and programs started
+/// through `env` are looked through.
+fn desktop_entry_program(contents: &str) -> Option<String> {
+    let mut group = "";
+
+    let exec = contents.lines().map(str::trim).find_map(|line| {
+        if line.starts_with('[') {
+            group = line;
+            return None;
+        }
+
+        match group {
+            "[Desktop Entry]" => line.strip_prefix("Exec="),
+            _ => None,
+        }
+    })?;
+
+    let mut words = exec_words(exec).into_iter().peekable();
+
+    if words
+        .peek()
+        .is_some_and(|word| word == "env" || word.ends_with("/env"))
+    {
+        words.next();
+
+        while words.next_if(|word| is_env_assignment(word)).is_some() {}
+    }
+
+    words.next()
+}
+
+/// Split the value of an `Exec=` key into words, removing the quotes around them.
+fn exec_words(exec: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut chars = exec.chars();
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            '\\' if quoted => word.get_or_insert_with(String::new).extend(chars.next()),
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    words.extend(word);
+    words
+}
+
+/// Whether `word` is a `NAME=value` argument to `env`.
+fn is_env_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Find a program in `$PATH`, unless it is already a path.
+pub(crate) fn which(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return path.is_file().then_some(path);
+    }
+
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(program))
+            .find(|path| path.is_file())
+    })
+}
+
+fn scan_desktop_entries() -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+
+    for dir in application_dirs() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "desktop") {
+                continue;
+            }
+
+            let Ok(contents) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+
+            let Some(program) = desktop_entry_program(&contents) else {
+                continue;
+            };
+
+            // Flatpak apps are found by scanning the installations directly.
+            if program.ends_with("flatpak") {
+                continue;
+            }
+
+            let Some(executable) = which(&program) else {
+                continue;
+            };
+
+            // `/usr/bin/<app>` is often a symlink or a wrapper script.
+            let resolved = super::resolve::resolve(&executable);
+
+            let app_dir = resolved.resources_dir.as_deref().and_then(Path::parent);
+
+            if let Some(candidate) = app_dir.and_then(candidate_from_app_dir) {
+                candidates.push(candidate);
+            }
+        }
+    }
+
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn desktop_entry_program_skips_actions_and_env() {
+        let cases = [
+            ("[Desktop Entry]\nExec=/opt/app/app %U\n", Some("/opt/app/app")),
+            ("[Desktop Entry]\nExec=discord\n", Some("discord")),
+            (
+                "[Desktop Entry]\nExec=\"/opt/My App/app\" --flag\n",
+                Some("/opt/My App/app"),
+            ),
+            (
+                "[Desktop Entry]\nExec=env FOO=1 BAR_2=x /opt/app/app %U\n",
+                Some("/opt/app/app"),
+            ),
+            (
+                "[Desktop Entry]\nExec=/usr/bin/env ELECTRON_OZONE=1 app\n",
+                Some("app"),
+            ),
+            // Actions come after the main group, but may also come first.
+            (
+                "[Desktop Action new]\nExec=/opt/app/helper --new\n\n[Desktop Entry]\nExec=/opt/app/app\n",
+                Some("/opt/app/app"),
+            ),
+            (
+                "[Desktop Entry]\nName=App\n\n[Desktop Action new]\nExec=/opt/app/app --new\n",
+                None,
+            ),
+            ("Exec=/opt/app/app\n", None),
+            ("[Desktop Entry]\nExec=env\n", None),
+        ];
+
+        for (contents, expected) in cases {
+            assert_eq!(
+                desktop_entry_program(contents).as_deref(),
+                expected,
+                "{contents:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn exec_words_unquotes() {
+        assert_eq!(
+            exec_words(r#"  "/a b/c" d\e "f\"g" "" "#),
+            ["/a b/c", r"d\e", "f\"g", ""]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Extending the hooks from a downstream library
+//!
+//! Launchers that build their own library around electron-hook can change what the app sees without
+//! forking it, by registering their own path rewrites and interposing extra functions, e.g. from a
+//! `#[ctor::ctor]` in their library.
+//!
+//! Rewrites are consulted by every path hook (`open`, `stat` and friends, and `uv_fs_lstat`) in the order
+//! they were registered, and the first one that doesn't [pass](Rewrite::Pass) wins. Only if they all pass
+//! are the [redirect](crate::redirect) rules applied.
+//!
+//! ```rust
+//! use electron_hook::extension::{register_rewrite, Rewrite};
+//!
+//! register_rewrite(|path| match path.to_bytes() {
+//!     b"/opt/discord/resources/splash.html" => {
+//!         Rewrite::Redirect(c"/home/CoolPerson/splash.html".into())
+//!     }
+//!     b"/etc/passwd" => Rewrite::Block,
+//!     _ => Rewrite::Pass,
+//! });
+//!
+//! let error = std::fs::File::open("/etc/passwd").unwrap_err();
+//! assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+//! ```
+
+use std::cell::Cell;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use crate::{Error, Result};
+
+/// What to do with a path the app is about to use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rewrite {
+    /// Leave the path alone, and let the next rewrite or the redirect rules decide.
+    Pass,
+    /// Use another path instead. It must be visible to the app, e.g. inside a Flatpak sandbox.
+    Redirect(CString),
+    /// Fail as if the path didn't exist, with `ENOENT`.
+    Block,
+}
+
+type RewriteFn = Box<dyn Fn(&CStr) -> Rewrite + Send + Sync>;
+
+static REWRITES: RwLock<Vec<RewriteFn>> = RwLock::new(Vec::new());
+
+/// Whether any rewrites are registered, so the hooks can skip the lock otherwise.
+static HAS_REWRITES: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// Whether this thread is running a rewrite, which may itself open or stat files.
+    static IN_REWRITE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Register a path rewrite, which runs after the ones registered before it.
+///
+/// Rewrites run inside the hooks, so they should be quick. Files they open or stat themselves are not
+/// rewritten, and a rewrite that panics is treated as [Rewrite::Pass].
+pub fn register_rewrite(rewrite: impl Fn(&CStr) -> Rewrite + Send + Sync + 'static) {
+    let mut rewrites = REWRITES
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    rewrites.push(Box::new(rewrite));
+    HAS_REWRITES.store(true, Ordering::Release);
+}
+
+/// Run the registered rewrites on `path`.
+pub(crate) fn rewrite(path: &CStr) -> Rewrite {
+    if !HAS_REWRITES.load(Ordering::Acquire) {
+        return Rewrite::Pass;
+    }
+
+    // The thread local may already be gone while the thread exits.
+    let Ok(false) = IN_REWRITE.try_with(|in_rewrite| in_rewrite.replace(true)) else {
+        return Rewrite::Pass;
+    };
+
+    let rewrites = REWRITES
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    let result = rewrites
+        .iter()
+        .map(|rewrite| {
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| rewrite(path)))
+                .unwrap_or(Rewrite::Pass)
+        })
+        .find(|result| *result != Rewrite::Pass)
+        .unwrap_or(Rewrite::Pass);
+
+    let _ = IN_REWRITE.try_with(|in_rewrite| in_rewrite.set(false));
+
+    result
+}
+
+#[link(name = "dl")]
+unsafe extern "C" {
+    unsafe fn dlsym(handle: *const c_void, symbol: *const c_char) -> *const c_void;
+}
+
+/// Installed interpositions, which are undone when dropped.
+static INTERPOSED: Mutex<Vec<retour::RawDetour>> = Mutex::new(Vec::new());
+
+/// Redirect every call to `symbol` in the app to `replacement`, returning a pointer to call the original.
+///
+/// This patches the function itself, so it also catches calls from inside its own library, which
+/// `LD_PRELOAD` can't. Functions electron-hook already hooks, like `open`, should use [register_rewrite]
+/// instead.
+///
+/// # Safety
+///
+/// `replacement` must be a function with the same signature and calling convention as `symbol`, and the
+/// returned pointer must be transmuted to that signature before it is called.
+///
+/// ```rust,ignore
+/// use electron_hook::extension::interpose;
+///
+/// type Getpid = unsafe extern "C" fn() -> libc::pid_t;
+///
+/// static ORIGINAL: std::sync::OnceLock<Getpid> = std::sync::OnceLock::new();
+///
+/// unsafe extern "C" fn getpid() -> libc::pid_t {
+///     ORIGINAL.get().map_or(0, |original| unsafe { original() })
+/// }
+///
+/// let original = unsafe { interpose(c"getpid", getpid as *const ())? };
+/// ORIGINAL.get_or_init(|| unsafe { std::mem::transmute::<*const (), Getpid>(original) });
+/// ```
+pub unsafe fn interpose(symbol: &CStr, replacement: *const ()) -> Result<*const ()> {
+    let interpose_error = |reason: String| Error::Interpose {
+        symbol: symbol.to_string_lossy().into_owned(),
+        reason,
+    };
+
+    let target = dlsym(libc::RTLD_DEFAULT, symbol.as_ptr());
+
+    if target.is_null() {
+        return Err(interpose_error("symbol not found".into()));
+    }
+
+    let detour = retour::RawDetour::new(target.cast(), replacement)
+        .map_err(|e| interpose_error(e.to_string()))?;
+
+    detour
+        .enable()
+        .map_err(|e| interpose_error(e.to_string()))?;
+
+    let original: *const () = detour.trampoline();
+
+    INTERPOSED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .push(detour);
+
+    Ok(original)
+}
@@ -0,0 +1,215 @@
+use std::path::{Path, PathBuf};
+
+use crate::{Error, FlatpakID, FlatpakTarget, Result};
+
+use super::discovery::{flatpak_installations, scan_dir};
+use super::sandbox::{Access, SandboxPaths};
+
+/// Ask the `flatpak` CLI whether `app_id` is installed with the given installation flag.
+///
+/// This catches installations that aren't in the default directories.
+fn flatpak_info(app_id: &str, installation: &str) -> bool {
+    std::process::Command::new("flatpak")
+        .args(["info", installation, app_id])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Find whether `app_id` is a user or system install, preferring user installs like `flatpak run` does.
+///
+/// `flatpak run` also accepts refs like `app.id//branch`, but only the app ID part is returned.
+pub(crate) fn resolve(app_id: &str) -> Result<FlatpakID> {
+    let (app_id, _, _) = crate::split_ref(app_id);
+
+    for (apps_dir, scope) in flatpak_installations() {
+        if apps_dir.join(app_id).join("current").exists() {
+            return Ok(scope(app_id.to_string()));
+        }
+    }
+
+    if flatpak_info(app_id, "--user") {
+        return Ok(FlatpakID::User(app_id.to_string()));
+    }
+
+    if flatpak_info(app_id, "--system") {
+        return Ok(FlatpakID::System(app_id.to_string()));
+    }
+
+    Err(Error::FlatpakNotInstalled(app_id.to_string()))
+}
+
+/// Find the app's `resources` directory, as the sandbox sees it under `/app`.
+pub(crate) fn resources_dir(flatpak: &FlatpakTarget) -> Option<PathBuf> {
+    let branch = flatpak.branch.as_deref();
+
+    for (apps_dir, _) in flatpak_installations() {
+        let app_dir = apps_dir.join(flatpak.id.id());
+
+        // `current` points at the default branch, otherwise deployments are in `<arch>/<branch>`.
+        let deployments = match branch {
+            None => vec![app_dir.join("current")],
+            Some(branch) => std::fs::read_dir(&app_dir)
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| {
+                    flatpak
+                        .arch
+                        .as_ref()
+                        .is_none_or(|arch| entry.file_name() == arch.as_str())
+                })
+                .map(|entry| entry.path().join(branch))
+                .collect(),
+        };
+
+        for deployment in deployments {
+            let files_dir = deployment.join("active/files");
+
+            let Some(candidate) = scan_dir(&files_dir).into_iter().next() else {
+                continue;
+            };
+
+            if let Ok(resources_dir) = candidate.resources_dir.strip_prefix(&files_dir) {
+                return Some(Path::new("/app").join(resources_dir));
+            }
+        }
+    }
+
+    None
+}
+
+/// Resolve the library path on the host.
+///
+/// Relative paths are looked up in the current directory first. Otherwise, we assume it's a library name in
+/// `/usr/lib`.
+pub(crate) fn host_library_path(library_path: &str) -> Result<PathBuf> {
+    let library = Path::new(library_path);
+
+    if library.is_absolute() {
+        return Ok(library.to_path_buf());
+    }
+
+    let current_dir =
+        std::env::current_dir().map_err(|_| Error::InvalidPath(library_path.into()))?;
+    let local_path = current_dir.join(library);
+
+    if local_path.is_file() {
+        Ok(local_path)
+    } else {
+        Ok(Path::new("/usr/lib").join(library))
+    }
+}
+
+/// Resolve symlinks (e.g. `/home` -> `/var/home` on Silverblue), so we grant the directory Flatpak will mount.
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Copy a file that the sandbox can't see into the electron-hook cache, which it can.
+///
+/// Files keep their name, in a directory named after a hash of their full path, so files with the same name
+/// from different places don't overwrite each other.
+pub(crate) fn stage(path: &Path) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::InvalidPath(path.into()))?;
+
+    let dir = crate::paths::sandbox_staging_dir()?.join(format!("{:016x}", path_hash(path)));
+    let dir = crate::paths::ensure_dir(dir)?;
+    let staged = dir.join(file_name);
+
+    std::fs::copy(path, &staged).map_err(|_| Error::SandboxUnreachable(path.into()))?;
+
+    Ok(staged)
+}
+
+/// A hash of `path` that stays the same between runs and Rust versions, unlike [std::hash::DefaultHasher].
+pub(crate) fn path_hash(path: &Path) -> u64 {
+    use std::os::unix::ffi::OsStrExt;
+
+    // 64-bit FNV-1a
+    path.as_os_str()
+        .as_bytes()
+        .iter()
+        .fold(0xcbf29ce484222325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// Expose a single file read-only, copying it somewhere shareable first if needed.
+pub(crate) fn expose_file(sandbox: &mut SandboxPaths, path: &Path) -> Result<PathBuf> {
+    let path = canonical(path);
+
+    if let Some(sandbox_path) = sandbox.expose(&path, Access::ReadOnly) {
+        return Ok(sandbox_path);
+    }
+
+    let staged = stage(&path)?;
+
+    sandbox
+        .expose(&staged, Access::ReadOnly)
+        .ok_or(Error::SandboxUnreachable(path))
+}
+
+/// Expose a directory. Directories are never copied, so this fails if the sandbox can't see it.
+pub(crate) fn expose_dir(
+    sandbox: &mut SandboxPaths,
+    dir: &Path,
+    access: Access,
+) -> Result<PathBuf> {
+    let dir = canonical(dir);
+
+    sandbox
+        .expose(&dir, access)
+        .ok_or(Error::SandboxUnreachable(dir))
+}
+
+/// Expose a file by granting read-only access to the directory containing it.
+///
+/// This keeps working if the file is replaced, e.g. when the ASAR is recreated.
+pub(crate) fn expose_in_dir(sandbox: &mut SandboxPaths, path: &Path) -> Result<PathBuf> {
+    let path = canonical(path);
+
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return Err(Error::InvalidPath(path));
+    };
+
+    match sandbox.expose(dir, Access::ReadOnly) {
+        Some(dir) => Ok(dir.join(file_name)),
+        None => expose_file(sandbox, &path),
+    }
+}
+
+/// Expose a file by granting `access` to the directory containing it.
+///
+/// Unlike [expose_in_dir], the file is never copied, since the app may need to write next to it.
+pub(crate) fn expose_in_dir_with(
+    sandbox: &mut SandboxPaths,
+    path: &Path,
+    access: Access,
+) -> Result<PathBuf> {
+    let path = canonical(path);
+
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return Err(Error::InvalidPath(path));
+    };
+
+    Ok(expose_dir(sandbox, dir, access)?.join(file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_hash_tells_files_with_the_same_name_apart() {
+        let a = path_hash(Path::new("/run/user/1000/mods/a/index.js"));
+        let b = path_hash(Path::new("/run/user/1000/mods/b/index.js"));
+
+        assert_ne!(a, b);
+        assert_eq!(a, path_hash(Path::new("/run/user/1000/mods/a/index.js")));
+    }
+}
@@ -1,18 +1,116 @@
-use std::ffi::{c_char, c_void};
+use std::ffi::{c_char, c_void, CStr, CString};
 
 use retour::static_detour;
 
+use super::extension::{self, Rewrite};
+use super::preload;
+use super::trace::{self, Hook};
+
 mod env {
     use std::sync::LazyLock;
 
+    // These are `None` if we were preloaded into something that wasn't started by the launcher.
+    //
+    // They use libc directly, since `std::env` holds a lock while calling the hooked `unsetenv`.
     macro_rules! lazy_env {
         ($name:expr) => {
-            LazyLock::new(|| std::env::var($name).unwrap())
+            LazyLock::new(|| unsafe {
+                let value = libc::getenv($name.as_ptr());
+                (!value.is_null()).then(|| std::ffi::CStr::from_ptr(value).to_owned())
+            })
         };
     }
 
-    pub static MODLOADER_ASAR_PATH: LazyLock<String> = lazy_env!("MODLOADER_ASAR_PATH");
-    pub static MODLOADER_LIBRARY_PATH: LazyLock<String> = lazy_env!("MODLOADER_LIBRARY_PATH");
+    pub static MODLOADER_LIBRARY_PATH: LazyLock<Option<std::ffi::CString>> =
+        lazy_env!(c"MODLOADER_LIBRARY_PATH");
+
+    static MODLOADER_REDIRECTS_JSON: LazyLock<Option<std::ffi::CString>> =
+        lazy_env!(c"MODLOADER_REDIRECTS");
+    static MODLOADER_ASAR_PATH: LazyLock<Option<std::ffi::CString>> = LazyLock::new(|| {
+        let path = MODLOADER_ASAR_PATH_VAR.clone()?;
+
+        // An in-memory ASAR is only open in the process the launcher handed it to. Its
+        // descendants inherit the variable, but fd `N` is closed or some other file there.
+        if path.to_bytes().starts_with(b"/proc/self/fd/")
+            && !crate::linux::memfd::is_memfd(&path, crate::linux::memfd::ASAR_NAME)
+        {
+            return None;
+        }
+
+        Some(path)
+    });
+    static MODLOADER_ASAR_PATH_VAR: LazyLock<Option<std::ffi::CString>> =
+        lazy_env!(c"MODLOADER_ASAR_PATH");
+    static MODLOADER_RESOURCES_DIR: LazyLock<Option<std::ffi::CString>> =
+        lazy_env!(c"MODLOADER_RESOURCES_DIR");
+    static APPDIR: LazyLock<Option<std::ffi::CString>> = lazy_env!(c"APPDIR");
+
+    pub static MODLOADER_REDIRECTS: LazyLock<Vec<crate::redirect::Rule>> = LazyLock::new(|| {
+        crate::redirect::from_vars(|name| {
+            let value = match name {
+                "MODLOADER_REDIRECTS" => &MODLOADER_REDIRECTS_JSON,
+                "MODLOADER_ASAR_PATH" => &MODLOADER_ASAR_PATH,
+                "MODLOADER_RESOURCES_DIR" => &MODLOADER_RESOURCES_DIR,
+                "APPDIR" => &APPDIR,
+                _ => return None,
+            };
+
+            value.as_ref()?.to_str().ok().map(str::to_string)
+        })
+    });
+}
+
+/// A path blocked by an [extension](super::extension) rewrite.
+struct Blocked;
+
+/// Where `path` should really be read from, if it is redirected, or `Err` if it is blocked.
+///
+/// Every hook goes through here, so they all agree on what is redirected. Extension rewrites come first,
+/// then the redirect rules.
+unsafe fn redirect(path: *const c_char) -> Result<Option<CString>, Blocked> {
+    if path.is_null() {
+        return Ok(None);
+    }
+
+    let path = CStr::from_ptr(path);
+
+    match extension::rewrite(path) {
+        Rewrite::Pass => Ok(crate::redirect::redirect_c_path(
+            &env::MODLOADER_REDIRECTS,
+            path,
+        )),
+        Rewrite::Redirect(redirect_to) => Ok(Some(redirect_to)),
+        Rewrite::Block => Err(Blocked),
+    }
+}
+
+/// What the hooks return for a blocked path.
+fn blocked(hook: Hook, path: *const c_char) -> i32 {
+    unsafe { *libc::__errno_location() = libc::ENOENT };
+    trace::record(hook, path, None, -1);
+    -1
+}
+
+/// The path the real function should be called with.
+fn target(path: *const c_char, redirect_to: &Option<CString>) -> *const c_char {
+    redirect_to
+        .as_ref()
+        .map_or(path, |redirect_to| redirect_to.as_ptr())
+}
+
+/// Whether `redirect_to` is a `/proc/self/fd/<fd>` link, like an in-memory ASAR.
+///
+/// The `lstat` family would describe the link itself instead of the file, so they follow it instead.
+fn follow_redirect(redirect_to: &Option<CString>) -> bool {
+    redirect_to
+        .as_ref()
+        .is_some_and(|redirect_to| redirect_to.to_bytes().starts_with(b"/proc/self/fd/"))
+}
+
+/// What the hooks return if the real function can't be found, which should never happen.
+fn unavailable() -> i32 {
+    unsafe { *libc::__errno_location() = libc::ENOSYS };
+    -1
 }
 
 #[link(name = "dl")]
@@ -20,6 +118,19 @@ unsafe extern "C" {
     unsafe fn dlsym(handle: *const c_void, symbol: *const c_char) -> *const c_void;
 }
 
+/// The next definition of a hooked libc function, i.e. the real one, looked up once.
+///
+/// This is `None` if there is no next definition, so the hook can fail instead of calling a null pointer.
+macro_rules! original {
+    ($symbol:literal as $ty:ty) => {{
+        static ORIGINAL: std::sync::LazyLock<Option<$ty>> = std::sync::LazyLock::new(|| unsafe {
+            let original = dlsym(libc::RTLD_NEXT, $symbol.as_ptr());
+            (!original.is_null()).then(|| std::mem::transmute::<*const c_void, $ty>(original))
+        });
+        *ORIGINAL
+    }};
+}
+
 unsafe extern "C" {
     #[link_name = "uv_fs_lstat"]
     unsafe fn original_uv_fs_lstat(
@@ -32,15 +143,23 @@ unsafe extern "C" {
 
 #[ctor::ctor]
 unsafe fn init_dynamic_hooks() {
+    // Panicking here would abort the app before it even starts, so carry on without this hook instead.
     #[allow(clippy::missing_transmute_annotations)]
-    UvFsLstatDetour
-        .initialize(
-            std::mem::transmute::<UvFsLstat, _>(original_uv_fs_lstat),
-            uv_fs_lstat,
-        )
-        .unwrap();
+    let initialized = UvFsLstatDetour.initialize(
+        std::mem::transmute::<UvFsLstat, _>(original_uv_fs_lstat),
+        uv_fs_lstat,
+    );
+
+    if initialized.is_ok() {
+        let _ = UvFsLstatDetour.enable();
+    }
+}
 
-    UvFsLstatDetour.enable().unwrap();
+// Read these while the environment is still the one we were started with.
+#[ctor::ctor]
+unsafe fn init_preload() {
+    std::sync::LazyLock::force(&env::MODLOADER_LIBRARY_PATH);
+    preload::init();
 }
 
 type UvFsLstat = unsafe extern "C" fn(
@@ -54,21 +173,202 @@ static_detour! {
     static UvFsLstatDetour: fn(*const c_void, *const c_void, *const c_char, *mut c_void) -> i32;
 }
 
+type UnsetEnv = unsafe extern "C" fn(*const c_char) -> i32;
+
 // This is a fix needed for flatpak support, as zypak is stripping our LD_PRELOAD incorrectly
 // See: https://github.com/refi64/zypak/issues/42
 #[no_mangle]
 unsafe extern "C" fn unsetenv(name: *const c_char) -> i32 {
-    let name_str = std::ffi::CStr::from_ptr(name).to_str().unwrap();
+    let Some(original_unsetenv) = original!(c"unsetenv" as UnsetEnv) else {
+        return unavailable();
+    };
+
+    if name.is_null() || CStr::from_ptr(name).to_bytes() != b"LD_PRELOAD" {
+        return original_unsetenv(name);
+    }
 
-    let original_unsetenv: unsafe extern "C" fn(*const c_char) -> i32 =
-        std::mem::transmute(dlsym(libc::RTLD_NEXT, c"unsetenv".as_ptr()));
+    // Without this, zypak's child processes would start without us, which is what BrowserOnly wants anyway.
+    let result = match &*env::MODLOADER_LIBRARY_PATH {
+        Some(library_path) if preload::policy() != crate::PreloadPolicy::BrowserOnly => {
+            restore_preload(library_path)
+        }
+        _ => original_unsetenv(name),
+    };
+
+    // Only LD_PRELOAD is traced, since std may hold its env lock while unsetting anything else.
+    trace::record(Hook::UnsetEnv, name, None, result.into());
+    result
+}
+
+/// Instead of unsetting LD_PRELOAD, drop only zypak's own libraries from it, and make sure ours is in it.
+unsafe fn restore_preload(library_path: &CStr) -> i32 {
+    let existing = libc::getenv(c"LD_PRELOAD".as_ptr());
+    let existing = match existing.is_null() {
+        true => &[][..],
+        false => CStr::from_ptr(existing).to_bytes(),
+    };
+
+    let others = preload::entries(existing).filter(|entry| {
+        let file_name = entry.rsplit(|&byte| byte == b'/').next().unwrap_or(entry);
+        !file_name.starts_with(b"libzypak")
+    });
+
+    let mut merged = [0u8; 4096];
+
+    // If it's too long to fit, at least keep ours.
+    let value = match preload::merge_entries_into(others, library_path.to_bytes(), &mut merged) {
+        Some(_) => merged.as_ptr().cast(),
+        None => library_path.as_ptr(),
+    };
+
+    libc::setenv(c"LD_PRELOAD".as_ptr(), value, 1)
+}
+
+// Programs outside the preload policy are started without our LD_PRELOAD entry and the MODLOADER_*
+// variables.
+//
+// These usually run in a forked child, so the scrubbed environment is built without the heap.
+
+unsafe extern "C" {
+    static environ: *const *const c_char;
+}
 
-    if name_str == "LD_PRELOAD" {
-        std::env::set_var("LD_PRELOAD", &*env::MODLOADER_LIBRARY_PATH);
-        return 0;
+/// The environment to start `path` with, scrubbed into `scrubbed` if it is outside the preload policy.
+///
+/// If we weren't preloaded by the launcher, e.g. in the launcher itself, or the environment can't be
+/// scrubbed, it is passed on unchanged.
+///
+/// exec only returns if it failed, so the call is recorded here, before it is made.
+unsafe fn exec_env(
+    hook: Hook,
+    path: *const c_char,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+    scrubbed: &mut preload::Scrubbed,
+) -> *const *const c_char {
+    trace::record(hook, path, None, 0);
+
+    if envp.is_null() || env::MODLOADER_LIBRARY_PATH.is_none() || preload::keeps_preload(path, argv)
+    {
+        return envp;
     }
 
-    original_unsetenv(name)
+    scrubbed.scrub(envp).unwrap_or(envp)
+}
+
+type Execve =
+    unsafe extern "C" fn(*const c_char, *const *const c_char, *const *const c_char) -> i32;
+
+#[no_mangle]
+unsafe extern "C" fn execve(
+    path: *const c_char,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) -> i32 {
+    let Some(original) = original!(c"execve" as Execve) else {
+        return unavailable();
+    };
+
+    let mut scrubbed = preload::Scrubbed::new();
+    original(
+        path,
+        argv,
+        exec_env(Hook::Execve, path, argv, envp, &mut scrubbed),
+    )
+}
+
+#[no_mangle]
+unsafe extern "C" fn execv(path: *const c_char, argv: *const *const c_char) -> i32 {
+    let Some(original) = original!(c"execve" as Execve) else {
+        return unavailable();
+    };
+
+    let mut scrubbed = preload::Scrubbed::new();
+    original(
+        path,
+        argv,
+        exec_env(Hook::Execv, path, argv, environ, &mut scrubbed),
+    )
+}
+
+// Chromium replaces `environ` and calls execvp, so the PATH lookup has to use the scrubbed environment.
+
+#[no_mangle]
+unsafe extern "C" fn execvp(file: *const c_char, argv: *const *const c_char) -> i32 {
+    let Some(original) = original!(c"execvpe" as Execve) else {
+        return unavailable();
+    };
+
+    let mut scrubbed = preload::Scrubbed::new();
+    original(
+        file,
+        argv,
+        exec_env(Hook::Execvp, file, argv, environ, &mut scrubbed),
+    )
+}
+
+#[no_mangle]
+unsafe extern "C" fn execvpe(
+    file: *const c_char,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) -> i32 {
+    let Some(original) = original!(c"execvpe" as Execve) else {
+        return unavailable();
+    };
+
+    let mut scrubbed = preload::Scrubbed::new();
+    original(
+        file,
+        argv,
+        exec_env(Hook::Execvpe, file, argv, envp, &mut scrubbed),
+    )
+}
+
+type PosixSpawn = unsafe extern "C" fn(
+    *mut libc::pid_t,
+    *const c_char,
+    *const libc::posix_spawn_file_actions_t,
+    *const libc::posix_spawnattr_t,
+    *const *const c_char,
+    *const *const c_char,
+) -> i32;
+
+#[no_mangle]
+unsafe extern "C" fn posix_spawn(
+    pid: *mut libc::pid_t,
+    path: *const c_char,
+    file_actions: *const libc::posix_spawn_file_actions_t,
+    attrp: *const libc::posix_spawnattr_t,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) -> i32 {
+    // posix_spawn returns the error instead of setting errno.
+    let Some(original) = original!(c"posix_spawn" as PosixSpawn) else {
+        return libc::ENOSYS;
+    };
+
+    let mut scrubbed = preload::Scrubbed::new();
+    let envp = exec_env(Hook::PosixSpawn, path, argv, envp, &mut scrubbed);
+    original(pid, path, file_actions, attrp, argv, envp)
+}
+
+#[no_mangle]
+unsafe extern "C" fn posix_spawnp(
+    pid: *mut libc::pid_t,
+    file: *const c_char,
+    file_actions: *const libc::posix_spawn_file_actions_t,
+    attrp: *const libc::posix_spawnattr_t,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) -> i32 {
+    let Some(original) = original!(c"posix_spawnp" as PosixSpawn) else {
+        return libc::ENOSYS;
+    };
+
+    let mut scrubbed = preload::Scrubbed::new();
+    let envp = exec_env(Hook::PosixSpawnp, file, argv, envp, &mut scrubbed);
+    original(pid, file, file_actions, attrp, argv, envp)
 }
 
 // make the linker happy... TODO: Can we compile without this?
@@ -88,70 +388,487 @@ fn uv_fs_lstat(
     path: *const c_char,
     buf: *mut c_void,
 ) -> i32 {
-    let path_str = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
-    if path_str.contains("resources/_app.asar") {
-        let redirect_to = path_str.replace("/_app.asar", "/app.asar");
-        let redirect_to_c = std::ffi::CString::new(redirect_to.as_str()).unwrap();
-        return UvFsLstatDetour.call(loop_, req, redirect_to_c.as_ptr(), buf);
+    // Only reachable through our exported symbol if the detour couldn't be set up.
+    if !UvFsLstatDetour.is_enabled() {
+        return -libc::ENOSYS;
     }
 
-    UvFsLstatDetour.call(loop_, req, path, buf)
+    // uv_fs_stat is in the Electron binary, which comes before us in the search order.
+    static UV_FS_STAT: std::sync::LazyLock<Option<UvFsLstat>> =
+        std::sync::LazyLock::new(|| unsafe {
+            let uv_fs_stat = dlsym(libc::RTLD_DEFAULT, c"uv_fs_stat".as_ptr());
+            (!uv_fs_stat.is_null())
+                .then(|| std::mem::transmute::<*const c_void, UvFsLstat>(uv_fs_stat))
+        });
+
+    let Ok(redirect_to) = (unsafe { redirect(path) }) else {
+        trace::record(Hook::UvFsLstat, path, None, (-libc::ENOENT).into());
+        return -libc::ENOENT;
+    };
+    let result = match *UV_FS_STAT {
+        Some(uv_fs_stat) if follow_redirect(&redirect_to) => unsafe {
+            uv_fs_stat(loop_, req, target(path, &redirect_to), buf)
+        },
+        _ => UvFsLstatDetour.call(loop_, req, target(path, &redirect_to), buf),
+    };
+    trace::record(Hook::UvFsLstat, path, redirect_to.as_deref(), result.into());
+    result
 }
 
-type XStat64 = unsafe extern "C" fn(i32, *const c_char, *mut libc::stat64) -> i64;
+/// Like [redirect], but for the `*at` functions, where relative paths are relative to a directory fd and
+/// can't be matched.
+unsafe fn redirect_absolute(path: *const c_char) -> Result<Option<CString>, Blocked> {
+    if path.is_null() || *path != b'/' as c_char {
+        return Ok(None);
+    }
+
+    redirect(path)
+}
+
+// Before glibc 2.33, stat() and friends were inline wrappers around these.
+
+type XStat64 = unsafe extern "C" fn(i32, *const c_char, *mut libc::stat64) -> i32;
 
 #[no_mangle]
-unsafe extern "C" fn __xstat64(ver: i32, path: *const c_char, out: *mut libc::stat64) -> i64 {
-    use std::sync::LazyLock;
+unsafe extern "C" fn __xstat64(ver: i32, path: *const c_char, out: *mut libc::stat64) -> i32 {
+    let Some(original) = original!(c"__xstat64" as XStat64) else {
+        return unavailable();
+    };
+
+    let Ok(redirect_to) = redirect(path) else {
+        return blocked(Hook::XStat64, path);
+    };
+    let result = original(ver, target(path, &redirect_to), out);
+    trace::record(Hook::XStat64, path, redirect_to.as_deref(), result.into());
+    result
+}
 
-    static ORIGINAL_XSTAT64: LazyLock<XStat64> = LazyLock::new(|| unsafe {
-        std::mem::transmute(dlsym(libc::RTLD_NEXT, c"__xstat64".as_ptr()))
-    });
+#[no_mangle]
+unsafe extern "C" fn __lxstat64(ver: i32, path: *const c_char, out: *mut libc::stat64) -> i32 {
+    let Some(original) = original!(c"__lxstat64" as XStat64) else {
+        return unavailable();
+    };
+
+    let Ok(redirect_to) = redirect(path) else {
+        return blocked(Hook::LXStat64, path);
+    };
+    let result = match original!(c"__xstat64" as XStat64) {
+        Some(xstat64) if follow_redirect(&redirect_to) => {
+            xstat64(ver, target(path, &redirect_to), out)
+        }
+        _ => original(ver, target(path, &redirect_to), out),
+    };
+    trace::record(Hook::LXStat64, path, redirect_to.as_deref(), result.into());
+    result
+}
 
-    let path_str = std::ffi::CStr::from_ptr(path).to_str().unwrap();
+// Since glibc 2.33, these are real functions.
 
-    // If calling _app.asar, return the original app.asar
-    if path_str.contains("resources/_app.asar") {
-        let redirect_to = path_str.replace("/_app.asar", "/app.asar");
-        let redirect_to_c = std::ffi::CString::new(redirect_to.as_str()).unwrap();
-        return ORIGINAL_XSTAT64(ver, redirect_to_c.as_ptr(), out);
-    }
+type Stat = unsafe extern "C" fn(*const c_char, *mut libc::stat) -> i32;
+type Stat64 = unsafe extern "C" fn(*const c_char, *mut libc::stat64) -> i32;
 
-    // If calling app.asar, return the custom app.asar
-    if path_str.contains("resources/app.asar") {
-        let asar_path_cstr = std::ffi::CString::new(env::MODLOADER_ASAR_PATH.as_str()).unwrap();
-        return ORIGINAL_XSTAT64(ver, asar_path_cstr.as_ptr(), out);
-    }
+#[no_mangle]
+unsafe extern "C" fn stat(path: *const c_char, out: *mut libc::stat) -> i32 {
+    let Some(original) = original!(c"stat" as Stat) else {
+        return unavailable();
+    };
+
+    let Ok(redirect_to) = redirect(path) else {
+        return blocked(Hook::Stat, path);
+    };
+    let result = original(target(path, &redirect_to), out);
+    trace::record(Hook::Stat, path, redirect_to.as_deref(), result.into());
+    result
+}
 
-    ORIGINAL_XSTAT64(ver, path, out)
+#[no_mangle]
+unsafe extern "C" fn stat64(path: *const c_char, out: *mut libc::stat64) -> i32 {
+    let Some(original) = original!(c"stat64" as Stat64) else {
+        return unavailable();
+    };
+
+    let Ok(redirect_to) = redirect(path) else {
+        return blocked(Hook::Stat64, path);
+    };
+    let result = original(target(path, &redirect_to), out);
+    trace::record(Hook::Stat64, path, redirect_to.as_deref(), result.into());
+    result
 }
 
-type Open64 = unsafe extern "C" fn(*const c_char, i32, i32) -> i32;
+#[no_mangle]
+unsafe extern "C" fn lstat(path: *const c_char, out: *mut libc::stat) -> i32 {
+    let Some(original) = original!(c"lstat" as Stat) else {
+        return unavailable();
+    };
+
+    let Ok(redirect_to) = redirect(path) else {
+        return blocked(Hook::LStat, path);
+    };
+    let result = match original!(c"stat" as Stat) {
+        Some(stat) if follow_redirect(&redirect_to) => stat(target(path, &redirect_to), out),
+        _ => original(target(path, &redirect_to), out),
+    };
+    trace::record(Hook::LStat, path, redirect_to.as_deref(), result.into());
+    result
+}
 
 #[no_mangle]
-unsafe extern "C" fn open64(path: *const c_char, flags: i32, mode: i32) -> i32 {
-    use std::sync::LazyLock;
+unsafe extern "C" fn lstat64(path: *const c_char, out: *mut libc::stat64) -> i32 {
+    let Some(original) = original!(c"lstat64" as Stat64) else {
+        return unavailable();
+    };
+
+    let Ok(redirect_to) = redirect(path) else {
+        return blocked(Hook::LStat64, path);
+    };
+    let result = match original!(c"stat64" as Stat64) {
+        Some(stat64) if follow_redirect(&redirect_to) => stat64(target(path, &redirect_to), out),
+        _ => original(target(path, &redirect_to), out),
+    };
+    trace::record(Hook::LStat64, path, redirect_to.as_deref(), result.into());
+    result
+}
 
-    static ORIGINAL_OPENAT64: LazyLock<Open64> = LazyLock::new(|| unsafe {
-        std::mem::transmute(dlsym(libc::RTLD_NEXT, c"open64".as_ptr()))
-    });
+// `fstatat` is what glibc calls the `newfstatat` syscall.
 
-    let path_str = std::ffi::CStr::from_ptr(path).to_str().unwrap();
+type FStatAt = unsafe extern "C" fn(i32, *const c_char, *mut libc::stat, i32) -> i32;
+type FStatAt64 = unsafe extern "C" fn(i32, *const c_char, *mut libc::stat64, i32) -> i32;
 
-    // If calling _app.asar, return the original app.asar
-    if path_str.contains("resources/_app.asar") {
-        let redirect_to = path_str.replace("/_app.asar", "/app.asar");
-        let redirect_to_c = std::ffi::CString::new(redirect_to.as_str()).unwrap();
+#[no_mangle]
+unsafe extern "C" fn fstatat(
+    dirfd: i32,
+    path: *const c_char,
+    out: *mut libc::stat,
+    flags: i32,
+) -> i32 {
+    let Some(original) = original!(c"fstatat" as FStatAt) else {
+        return unavailable();
+    };
+
+    let Ok(redirect_to) = redirect_absolute(path) else {
+        return blocked(Hook::FStatAt, path);
+    };
+    let flags = match follow_redirect(&redirect_to) {
+        true => flags & !libc::AT_SYMLINK_NOFOLLOW,
+        false => flags,
+    };
+    let result = original(dirfd, target(path, &redirect_to), out, flags);
+    trace::record(Hook::FStatAt, path, redirect_to.as_deref(), result.into());
+    result
+}
+
+#[no_mangle]
+unsafe extern "C" fn fstatat64(
+    dirfd: i32,
+    path: *const c_char,
+    out: *mut libc::stat64,
+    flags: i32,
+) -> i32 {
+    let Some(original) = original!(c"fstatat64" as FStatAt64) else {
+        return unavailable();
+    };
+
+    let Ok(redirect_to) = redirect_absolute(path) else {
+        return blocked(Hook::FStatAt64, path);
+    };
+    let flags = match follow_redirect(&redirect_to) {
+        true => flags & !libc::AT_SYMLINK_NOFOLLOW,
+        false => flags,
+    };
+    let result = original(dirfd, target(path, &redirect_to), out, flags);
+    trace::record(Hook::FStatAt64, path, redirect_to.as_deref(), result.into());
+    result
+}
 
-        return ORIGINAL_OPENAT64(redirect_to_c.as_ptr(), flags, mode);
+type Statx = unsafe extern "C" fn(i32, *const c_char, i32, u32, *mut c_void) -> i32;
+
+#[no_mangle]
+unsafe extern "C" fn statx(
+    dirfd: i32,
+    path: *const c_char,
+    flags: i32,
+    mask: u32,
+    out: *mut c_void,
+) -> i32 {
+    let Some(original) = original!(c"statx" as Statx) else {
+        return unavailable();
+    };
+
+    let Ok(redirect_to) = redirect_absolute(path) else {
+        return blocked(Hook::Statx, path);
+    };
+    let flags = match follow_redirect(&redirect_to) {
+        true => flags & !libc::AT_SYMLINK_NOFOLLOW,
+        false => flags,
+    };
+    let result = original(dirfd, target(path, &redirect_to), flags, mask, out);
+    trace::record(Hook::Statx, path, redirect_to.as_deref(), result.into());
+    result
+}
+
+type Access = unsafe extern "C" fn(*const c_char, i32) -> i32;
+
+#[no_mangle]
+unsafe extern "C" fn access(path: *const c_char, mode: i32) -> i32 {
+    let Some(original) = original!(c"access" as Access) else {
+        return unavailable();
+    };
+
+    let Ok(redirect_to) = redirect(path) else {
+        return blocked(Hook::Access, path);
+    };
+    let result = original(target(path, &redirect_to), mode);
+    trace::record(Hook::Access, path, redirect_to.as_deref(), result.into());
+    result
+}
+
+// `mode` is variadic in C, but it's always passed in the same register, and is only read by the real
+// function when it's needed.
+
+type Open = unsafe extern "C" fn(*const c_char, i32, u32) -> i32;
+type OpenAt = unsafe extern "C" fn(i32, *const c_char, i32, u32) -> i32;
+
+#[no_mangle]
+unsafe extern "C" fn open(path: *const c_char, flags: i32, mode: u32) -> i32 {
+    let Some(original) = original!(c"open" as Open) else {
+        return unavailable();
+    };
+
+    let Ok(redirect_to) = redirect(path) else {
+        return blocked(Hook::Open, path);
+    };
+    let result = original(target(path, &redirect_to), flags, mode);
+    trace::record(Hook::Open, path, redirect_to.as_deref(), result.into());
+    result
+}
+
+#[no_mangle]
+unsafe extern "C" fn open64(path: *const c_char, flags: i32, mode: u32) -> i32 {
+    let Some(original) = original!(c"open64" as Open) else {
+        return unavailable();
+    };
+
+    let Ok(redirect_to) = redirect(path) else {
+        return blocked(Hook::Open64, path);
+    };
+    let result = original(target(path, &redirect_to), flags, mode);
+    trace::record(Hook::Open64, path, redirect_to.as_deref(), result.into());
+    result
+}
+
+#[no_mangle]
+unsafe extern "C" fn openat(dirfd: i32, path: *const c_char, flags: i32, mode: u32) -> i32 {
+    let Some(original) = original!(c"openat" as OpenAt) else {
+        return unavailable();
+    };
+
+    let Ok(redirect_to) = redirect_absolute(path) else {
+        return blocked(Hook::OpenAt, path);
+    };
+    let result = original(dirfd, target(path, &redirect_to), flags, mode);
+    trace::record(Hook::OpenAt, path, redirect_to.as_deref(), result.into());
+    result
+}
+
+#[no_mangle]
+unsafe extern "C" fn openat64(dirfd: i32, path: *const c_char, flags: i32, mode: u32) -> i32 {
+    let Some(original) = original!(c"openat64" as OpenAt) else {
+        return unavailable();
+    };
+
+    let Ok(redirect_to) = redirect_absolute(path) else {
+        return blocked(Hook::OpenAt64, path);
+    };
+    let result = original(dirfd, target(path, &redirect_to), flags, mode);
+    trace::record(Hook::OpenAt64, path, redirect_to.as_deref(), result.into());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+
+    /// The `ver` argument of `__xstat64` for `struct stat64`.
+    const STAT_VER: i32 = if cfg!(target_arch = "x86_64") { 1 } else { 0 };
+
+    fn errno() -> i32 {
+        std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+    }
+
+    /// A file whose name isn't valid UTF-8, with 9 bytes in it, and a path next to it that doesn't exist.
+    fn non_utf8_file(test: &str) -> (PathBuf, CString, CString) {
+        let dir =
+            std::env::temp_dir().join(format!("electron-hook-hooks-{}-{test}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join(OsStr::from_bytes(b"app-\xff.asar"));
+        std::fs::write(&path, b"not utf-8").unwrap();
+
+        let missing = dir.join(OsStr::from_bytes(b"missing-\xfe.asar"));
+
+        (
+            dir,
+            CString::new(path.as_os_str().as_bytes()).unwrap(),
+            CString::new(missing.as_os_str().as_bytes()).unwrap(),
+        )
+    }
+
+    /// The hooks should behave exactly like libc when the launcher didn't start us.
+    fn assert_not_launched() {
+        for name in [
+            "MODLOADER_REDIRECTS",
+            "MODLOADER_ASAR_PATH",
+            "MODLOADER_LIBRARY_PATH",
+            "MODLOADER_RESOURCES_DIR",
+        ] {
+            assert!(std::env::var_os(name).is_none(), "{name} is set");
+        }
+
+        assert!(env::MODLOADER_REDIRECTS.is_empty());
+        assert!(env::MODLOADER_LIBRARY_PATH.is_none());
+    }
+
+    #[test]
+    fn stat_passes_non_utf8_paths_through() {
+        assert_not_launched();
+        let (dir, path, missing) = non_utf8_file("stat");
+
+        unsafe {
+            let mut out = std::mem::zeroed::<libc::stat>();
+
+            assert_eq!(stat(path.as_ptr(), &mut out), 0);
+            assert_eq!(out.st_size, 9);
+
+            assert_eq!(stat(missing.as_ptr(), &mut out), -1);
+            assert_eq!(errno(), libc::ENOENT);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn xstat64_passes_non_utf8_paths_through() {
+        assert_not_launched();
+        let (dir, path, missing) = non_utf8_file("xstat64");
+
+        unsafe {
+            let mut out = std::mem::zeroed::<libc::stat64>();
+            let result = __xstat64(STAT_VER, path.as_ptr(), &mut out);
+
+            // glibc 2.33 and later only keep it for old binaries, so it may not be found.
+            match original!(c"__xstat64" as XStat64) {
+                Some(_) => {
+                    assert_eq!(result, 0);
+                    assert_eq!(out.st_size, 9);
+
+                    assert_eq!(__xstat64(STAT_VER, missing.as_ptr(), &mut out), -1);
+                    assert_eq!(errno(), libc::ENOENT);
+                }
+                None => {
+                    assert_eq!(result, -1);
+                    assert_eq!(errno(), libc::ENOSYS);
+                }
+            }
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn open64_passes_non_utf8_paths_through() {
+        assert_not_launched();
+        let (dir, path, missing) = non_utf8_file("open64");
+
+        unsafe {
+            let fd = open64(path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC, 0);
+            assert!(fd >= 0);
+
+            let mut buf = [0u8; 16];
+            let read = libc::read(fd, buf.as_mut_ptr().cast(), buf.len());
+            libc::close(fd);
+
+            assert_eq!(&buf[..read as usize], b"not utf-8");
+
+            assert_eq!(open64(missing.as_ptr(), libc::O_RDONLY, 0), -1);
+            assert_eq!(errno(), libc::ENOENT);
+
+            assert_eq!(open64(std::ptr::null(), libc::O_RDONLY, 0), -1);
+            assert_eq!(errno(), libc::EFAULT);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Run the rest of `test` in a child process if this isn't one already, for tests that change the
+    /// environment the other tests read from their threads. Returns whether this is the child.
+    fn in_child_process(test: &str) -> bool {
+        if std::env::var_os("ELECTRON_HOOK_TEST_CHILD").is_some() {
+            return true;
+        }
+
+        let (_, module) = module_path!().split_once("::").unwrap();
+
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([&format!("{module}::{test}"), "--exact", "--test-threads=1"])
+            .env("ELECTRON_HOOK_TEST_CHILD", "1")
+            .output()
+            .unwrap();
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            output.status.success() && stdout.contains("1 passed"),
+            "{test} failed in a child process:\n{stdout}{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        false
     }
 
-    // If calling app.asar, return the custom app.asar
-    if path_str.contains("resources/app.asar") {
-        let redirect_to = std::ffi::CString::new(env::MODLOADER_ASAR_PATH.as_str()).unwrap();
+    #[test]
+    fn unsetenv_passes_through() {
+        if !in_child_process("unsetenv_passes_through") {
+            return;
+        }
+
+        assert_not_launched();
+
+        let name = OsStr::from_bytes(b"ELECTRON_HOOK_TEST_\xff");
+        std::env::set_var(name, "1");
+
+        let c_name = CString::new(name.as_bytes()).unwrap();
+
+        unsafe {
+            assert_eq!(unsetenv(c_name.as_ptr()), 0);
+            assert!(libc::getenv(c_name.as_ptr()).is_null());
+        }
 
-        return ORIGINAL_OPENAT64(redirect_to.as_ptr(), flags, mode);
+        // Without MODLOADER_LIBRARY_PATH, there is nothing to restore, so LD_PRELOAD is really unset.
+        std::env::set_var("LD_PRELOAD", "/tmp/libzypak-preload-host.so");
+
+        unsafe {
+            assert_eq!(unsetenv(c"LD_PRELOAD".as_ptr()), 0);
+            assert!(libc::getenv(c"LD_PRELOAD".as_ptr()).is_null());
+        }
     }
 
-    ORIGINAL_OPENAT64(path, flags, mode)
+    #[test]
+    fn spawn_keeps_the_environment_without_library() {
+        assert_not_launched();
+
+        // This is what the launcher does, and it's linked with the hooks too.
+        let output = std::process::Command::new("env")
+            .env("MODLOADER_ASAR_PATH", "/tmp/mod.asar")
+            .env("LD_PRELOAD", "/tmp/libelectron_hook.so")
+            .output()
+            .unwrap();
+
+        let env = String::from_utf8_lossy(&output.stdout);
+
+        assert!(env.contains("MODLOADER_ASAR_PATH=/tmp/mod.asar\n"));
+        assert!(env.contains("LD_PRELOAD=/tmp/libelectron_hook.so\n"));
+    }
 }
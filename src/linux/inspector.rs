@@ -0,0 +1,832 @@
+//! Injecting the template through the Node.js inspector
+//!
+//! The app is started with `--inspect-brk`, so its main process waits for a debugger before running any
+//! JavaScript. We connect to it over the Chrome DevTools Protocol, evaluate the template where it paused,
+//! close the inspector and let it carry on.
+//!
+//! This only needs a WebSocket client for a single local connection, so it is hand-rolled rather than
+//! pulling in an HTTP and WebSocket stack.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::fuses::{Fuse, Fuses};
+use crate::{Error, Launcher, Result};
+
+/// How long the app has to start its inspector, and to answer each message.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The largest message we accept. The inspector's answers are small, so this only stops a misbehaving peer
+/// from making us allocate whatever it claims to send.
+const MAX_MESSAGE: u64 = 16 << 20;
+
+/// Whether the Electron binary at `executable` honours `--inspect` and friends.
+pub(crate) fn allowed(executable: &Path) -> bool {
+    Fuses::read(executable)
+        .ok()
+        .and_then(|fuses| fuses.get(Fuse::EnableNodeCliInspectArguments))
+        .unwrap_or(false)
+}
+
+/// The JavaScript to evaluate in the paused main process: the ASAR template, or else the mod entrypoint.
+pub(crate) fn expression(launcher: &Launcher) -> String {
+    let template = super::node_options::template(launcher)
+        .map(|template| String::from_utf8_lossy(&template).into_owned())
+        .unwrap_or_else(|| "require(process.env.MODLOADER_MOD_ENTRYPOINT);".into());
+
+    // Close the inspector once the app is running again, so nothing else can attach to it.
+    format!(
+        "(() => {{\n{template}\n}})();\n\
+         setImmediate(() => {{ try {{ require(\"inspector\").close(); }} catch {{ process._debugEnd?.(); }} }});"
+    )
+}
+
+fn inspector_error(reason: impl Into<String>) -> Error {
+    Error::Inspector(reason.into())
+}
+
+/// Evaluate `expression` in the main process of the app started as `pid`, whose inspector was told to
+/// listen on `address`.
+pub(crate) fn inject(address: SocketAddr, pid: u32, expression: &str) -> Result<()> {
+    let deadline = Instant::now() + TIMEOUT;
+
+    // The inspector only starts listening once the app has started up a bit.
+    let url = loop {
+        let url = app_address(address, pid)
+            .ok_or_else(|| {
+                inspector_error(format!("nothing started as {pid} listens on {address}"))
+            })
+            .and_then(debugger_url);
+
+        match url {
+            Ok(url) => break url,
+            Err(e) if Instant::now() >= deadline => return Err(e),
+            Err(_) => std::thread::sleep(Duration::from_millis(100)),
+        }
+    };
+
+    let mut socket = WebSocket::connect(&url)?;
+
+    socket.call(1, "Debugger.enable", json!({}))?;
+
+    // `--inspect-brk` waits for this before pausing on the first line.
+    socket.send(&json!({ "id": 2, "method": "Runtime.runIfWaitingForDebugger" }))?;
+
+    let paused = socket.wait_for(|message| message["method"] == "Debugger.paused")?;
+
+    let call_frame_id = paused["params"]["callFrames"][0]["callFrameId"]
+        .as_str()
+        .ok_or_else(|| inspector_error("paused without a call frame"))?
+        .to_string();
+
+    let result = socket.call(
+        3,
+        "Debugger.evaluateOnCallFrame",
+        json!({ "callFrameId": call_frame_id, "expression": expression }),
+    )?;
+
+    if let Some(exception) = result.get("exceptionDetails") {
+        let description = exception["exception"]["description"]
+            .as_str()
+            .or_else(|| exception["text"].as_str())
+            .unwrap_or("unknown exception");
+
+        return Err(inspector_error(format!("template threw: {description}")));
+    }
+
+    socket.call(4, "Debugger.resume", json!({}))?;
+
+    socket.close();
+
+    Ok(())
+}
+
+/// Where the inspector of the app started as `pid` listens, if it does yet.
+///
+/// Only the listening sockets of `pid` and its descendants count, so whatever else listens on the port
+/// can't be mistaken for the app. With port 0, the app picks a free port itself, which is found here.
+fn app_address(address: SocketAddr, pid: u32) -> Option<SocketAddr> {
+    let sockets = socket_inodes(&process_tree(pid));
+
+    let table = match address {
+        SocketAddr::V4(_) => "/proc/net/tcp",
+        SocketAddr::V6(_) => "/proc/net/tcp6",
+    };
+
+    // Each line is `sl local_address rem_address st tx_queue:rx_queue tr:tm->when retrnsmt uid timeout inode ...`.
+    std::fs::read_to_string(table)
+        .ok()?
+        .lines()
+        .skip(1)
+        .find_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let (ip, port) = fields.get(1)?.split_once(':')?;
+            let port = u16::from_str_radix(port, 16).ok()?;
+            let listening = *fields.get(3)? == "0A";
+            let inode = fields.get(9)?.parse::<u64>().ok()?;
+
+            let matches = listening
+                && proc_net_ip(ip)? == address.ip()
+                && (address.port() == 0 || port == address.port())
+                && sockets.contains(&inode);
+
+            matches.then(|| SocketAddr::new(address.ip(), port))
+        })
+}
+
+/// Parse an address from `/proc/net/tcp{,6}`, which are printed as 32-bit words in host byte order.
+fn proc_net_ip(hex: &str) -> Option<IpAddr> {
+    let bytes = (0..hex.len())
+        .step_by(8)
+        .map(|i| {
+            let word = u32::from_str_radix(hex.get(i..i + 8)?, 16).ok()?;
+            Some(word.to_ne_bytes())
+        })
+        .collect::<Option<Vec<_>>>()?
+        .concat();
+
+    match bytes.len() {
+        4 => Some(Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?).into()),
+        16 => Some(Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?).into()),
+        _ => None,
+    }
+}
+
+/// `pid` and its descendants, e.g. when the app was started through a wrapper that forks.
+fn process_tree(pid: u32) -> Vec<u32> {
+    let mut children = HashMap::<u32, Vec<u32>>::new();
+
+    for entry in std::fs::read_dir("/proc").into_iter().flatten().flatten() {
+        let Some(child) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        else {
+            continue;
+        };
+
+        // The parent follows the command name, which can contain anything, and the state.
+        let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+            continue;
+        };
+
+        let parent = stat
+            .rsplit_once(')')
+            .and_then(|(_, rest)| rest.split_whitespace().nth(1))
+            .and_then(|parent| parent.parse().ok());
+
+        if let Some(parent) = parent {
+            children.entry(parent).or_default().push(child);
+        }
+    }
+
+    let mut tree = vec![pid];
+    let mut i = 0;
+
+    while let Some(&pid) = tree.get(i) {
+        tree.extend(children.get(&pid).into_iter().flatten());
+        i += 1;
+    }
+
+    tree
+}
+
+/// The inodes of the sockets `pids` have open.
+fn socket_inodes(pids: &[u32]) -> HashSet<u64> {
+    pids.iter()
+        .filter_map(|pid| std::fs::read_dir(format!("/proc/{pid}/fd")).ok())
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let target = std::fs::read_link(entry.path()).ok()?;
+            let inode = target
+                .to_str()?
+                .strip_prefix("socket:[")?
+                .strip_suffix(']')?;
+            inode.parse().ok()
+        })
+        .collect()
+}
+
+/// Ask the inspector's HTTP endpoint for the WebSocket URL of the main process.
+fn debugger_url(address: SocketAddr) -> Result<String> {
+    let mut stream = TcpStream::connect_timeout(&address, Duration::from_secs(1))
+        .map_err(|e| inspector_error(e.to_string()))?;
+
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .map_err(|e| inspector_error(e.to_string()))?;
+
+    write!(
+        stream,
+        "GET /json/list HTTP/1.1\r\nHost: {address}\r\nConnection: close\r\n\r\n"
+    )
+    .map_err(|e| inspector_error(e.to_string()))?;
+
+    let mut reader = BufReader::new(stream);
+    let content_length = read_headers(&mut reader)?.iter().find_map(|header| {
+        let (name, value) = header.split_once(':')?;
+        name.eq_ignore_ascii_case("content-length")
+            .then(|| value.trim().parse::<u64>().ok())?
+    });
+
+    if content_length.is_some_and(|len| len > MAX_MESSAGE) {
+        return Err(inspector_error(format!(
+            "message larger than {MAX_MESSAGE} bytes"
+        )));
+    }
+
+    let mut body = Vec::new();
+
+    // Without a length, the body runs until the connection is closed.
+    let mut reader = reader.take(content_length.unwrap_or(MAX_MESSAGE + 1));
+
+    reader
+        .read_to_end(&mut body)
+        .map_err(|e| inspector_error(e.to_string()))?;
+
+    match content_length {
+        Some(len) if body.len() as u64 != len => {
+            return Err(inspector_error("the inspector closed the connection"));
+        }
+        None if body.len() as u64 > MAX_MESSAGE => {
+            return Err(inspector_error(format!(
+                "message larger than {MAX_MESSAGE} bytes"
+            )));
+        }
+        _ => {}
+    }
+
+    let targets: Value =
+        serde_json::from_slice(&body).map_err(|e| inspector_error(e.to_string()))?;
+
+    targets
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find_map(|target| target["webSocketDebuggerUrl"].as_str())
+        .map(str::to_string)
+        .ok_or_else(|| inspector_error("no debugging targets"))
+}
+
+/// Read an HTTP response's status line and headers, failing unless the status is `2xx` or `101`.
+fn read_headers(reader: &mut impl BufRead) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+
+    loop {
+        let mut line = String::new();
+
+        reader
+            .read_line(&mut line)
+            .map_err(|e| inspector_error(e.to_string()))?;
+
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            break;
+        }
+
+        lines.push(line.to_string());
+    }
+
+    let status = lines
+        .first()
+        .and_then(|status| status.split_whitespace().nth(1))
+        .unwrap_or_default();
+
+    if !(status.starts_with('2') || status == "101") {
+        return Err(inspector_error(format!(
+            "unexpected HTTP status {status:?}"
+        )));
+    }
+
+    Ok(lines.split_off(1))
+}
+
+/// A client for a single WebSocket connection, with just enough of RFC 6455 for the DevTools protocol.
+struct WebSocket {
+    reader: BufReader<TcpStream>,
+    stream: TcpStream,
+}
+
+impl WebSocket {
+    fn connect(url: &str) -> Result<Self> {
+        let rest = url
+            .strip_prefix("ws://")
+            .ok_or_else(|| inspector_error(format!("unsupported URL {url}")))?;
+
+        let (host, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, "/"),
+        };
+
+        let stream = TcpStream::connect(host).map_err(|e| inspector_error(e.to_string()))?;
+
+        stream
+            .set_read_timeout(Some(TIMEOUT))
+            .map_err(|e| inspector_error(e.to_string()))?;
+
+        let mut socket = Self {
+            reader: BufReader::new(
+                stream
+                    .try_clone()
+                    .map_err(|e| inspector_error(e.to_string()))?,
+            ),
+            stream,
+        };
+
+        // The key only has to be 16 random-looking bytes, so the time is good enough.
+        let key = base64(&nonce().to_le_bytes());
+
+        write!(
+            socket.stream,
+            "GET {path} HTTP/1.1\r\nHost: {host}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {key}\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        )
+        .map_err(|e| inspector_error(e.to_string()))?;
+
+        read_headers(&mut socket.reader)?;
+
+        Ok(socket)
+    }
+
+    fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<()> {
+        let mut frame = vec![0x80 | opcode];
+
+        // Clients must mask every frame.
+        match payload.len() {
+            len @ 0..=125 => frame.push(0x80 | len as u8),
+            len @ 126..=0xFFFF => {
+                frame.push(0x80 | 126);
+                frame.extend((len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend((len as u64).to_be_bytes());
+            }
+        }
+
+        let mask = (nonce() as u32).to_be_bytes();
+        frame.extend(mask);
+        frame.extend(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte ^ mask[i % 4]),
+        );
+
+        self.stream
+            .write_all(&frame)
+            .map_err(|e| inspector_error(e.to_string()))
+    }
+
+    fn send(&mut self, message: &Value) -> Result<()> {
+        self.send_frame(0x1, message.to_string().as_bytes())
+    }
+
+    /// Read the next text message, answering pings on the way.
+    fn receive(&mut self) -> Result<Value> {
+        let mut message = Vec::new();
+
+        loop {
+            let mut header = [0u8; 2];
+            self.read_exact(&mut header)?;
+
+            let fin = header[0] & 0x80 != 0;
+            let opcode = header[0] & 0x0F;
+            let masked = header[1] & 0x80 != 0;
+
+            let len = match header[1] & 0x7F {
+                126 => {
+                    let mut len = [0u8; 2];
+                    self.read_exact(&mut len)?;
+                    u16::from_be_bytes(len).into()
+                }
+                127 => {
+                    let mut len = [0u8; 8];
+                    self.read_exact(&mut len)?;
+                    u64::from_be_bytes(len)
+                }
+                len => len.into(),
+            };
+
+            if len > MAX_MESSAGE - message.len() as u64 {
+                return Err(inspector_error(format!(
+                    "message larger than {MAX_MESSAGE} bytes"
+                )));
+            }
+
+            let mut mask = [0u8; 4];
+            if masked {
+                self.read_exact(&mut mask)?;
+            }
+
+            let mut payload = vec![0u8; len as usize];
+            self.read_exact(&mut payload)?;
+
+            if masked {
+                payload
+                    .iter_mut()
+                    .enumerate()
+                    .for_each(|(i, byte)| *byte ^= mask[i % 4]);
+            }
+
+            match opcode {
+                0x0 | 0x1 => message.extend(payload),
+                0x8 => return Err(inspector_error("the inspector closed the connection")),
+                0x9 => self.send_frame(0xA, &payload)?,
+                _ => {}
+            }
+
+            if fin && matches!(opcode, 0x0 | 0x1) {
+                return serde_json::from_slice(&message)
+                    .map_err(|e| inspector_error(e.to_string()));
+            }
+        }
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.reader
+            .read_exact(buf)
+            .map_err(|e| inspector_error(e.to_string()))
+    }
+
+    /// Read messages until one matches `matches`, skipping events we don't care about.
+    fn wait_for(&mut self, matches: impl Fn(&Value) -> bool) -> Result<Value> {
+        loop {
+            let message = self.receive()?;
+
+            if matches(&message) {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Call `method` and wait for its result.
+    fn call(&mut self, id: u64, method: &str, params: Value) -> Result<Value> {
+        self.send(&json!({ "id": id, "method": method, "params": params }))?;
+
+        let response = self.wait_for(|message| message["id"] == id)?;
+
+        if let Some(error) = response.get("error") {
+            return Err(inspector_error(format!("{method} failed: {error}")));
+        }
+
+        Ok(response["result"].clone())
+    }
+
+    fn close(mut self) {
+        let _ = self.send_frame(0x8, &[]);
+    }
+}
+
+/// Some bits that differ between calls, for the WebSocket key and masks.
+fn nonce() -> u128 {
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    time ^ ((std::process::id() as u128) << 64)
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::new();
+
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &byte)| n | (byte as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    /// How the mock inspector answers `Debugger.evaluateOnCallFrame`.
+    #[derive(Clone, Copy)]
+    enum Evaluation {
+        Returns,
+        Throws,
+        Closes,
+        SendsTooMuch,
+    }
+
+    /// Read an HTTP request, returning its request line and headers.
+    fn read_request(reader: &mut impl BufRead) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+
+            match line.trim_end() {
+                "" => return lines,
+                line => lines.push(line.to_string()),
+            }
+        }
+    }
+
+    /// A mock inspector with a single target, which pauses like `--inspect-brk` does.
+    ///
+    /// Returns its address, and the expression the client evaluated once it's done.
+    fn mock_inspector(evaluation: Evaluation) -> (SocketAddr, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = read_request(&mut BufReader::new(stream.try_clone().unwrap()));
+            assert!(request[0].starts_with("GET /json/list "));
+
+            let targets = json!([{
+                "type": "node",
+                "webSocketDebuggerUrl": format!("ws://{address}/3c4e2d0a"),
+            }])
+            .to_string();
+
+            write!(
+                stream,
+                "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{targets}",
+                targets.len()
+            )
+            .unwrap();
+            drop(stream);
+
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let request = read_request(&mut reader);
+            assert!(request[0].starts_with("GET /3c4e2d0a "));
+            assert!(request.iter().any(|header| header == "Upgrade: websocket"));
+
+            // Our client doesn't check the accept key.
+            let mut socket = WebSocket { reader, stream };
+            socket
+                .stream
+                .write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n")
+                .unwrap();
+
+            let mut expression = String::new();
+
+            loop {
+                let message = match socket.receive() {
+                    Ok(message) => message,
+                    // The client closes the connection once it's done.
+                    Err(_) => return expression,
+                };
+
+                let id = message["id"].clone();
+
+                match message["method"].as_str().unwrap() {
+                    "Debugger.enable" => {
+                        // Events the client should skip.
+                        socket.send_frame(0x9, b"ping").unwrap();
+                        socket
+                            .send(&json!({ "method": "Debugger.scriptParsed", "params": {} }))
+                            .unwrap();
+                        socket
+                            .send(&json!({ "id": id, "result": { "debuggerId": "1" } }))
+                            .unwrap();
+                    }
+                    "Runtime.runIfWaitingForDebugger" => {
+                        socket.send(&json!({ "id": id, "result": {} })).unwrap();
+                        socket
+                            .send(&json!({
+                                "method": "Debugger.paused",
+                                "params": { "callFrames": [{ "callFrameId": "frame-0" }] },
+                            }))
+                            .unwrap();
+                    }
+                    "Debugger.evaluateOnCallFrame" => {
+                        assert_eq!(message["params"]["callFrameId"], "frame-0");
+                        expression = message["params"]["expression"].as_str().unwrap().into();
+
+                        match evaluation {
+                            Evaluation::Returns => socket.send(&json!({
+                                "id": id,
+                                "result": { "result": { "type": "undefined" } },
+                            })),
+                            Evaluation::Throws => socket.send(&json!({
+                                "id": id,
+                                "result": {
+                                    "result": { "type": "object" },
+                                    "exceptionDetails": {
+                                        "text": "Uncaught",
+                                        "exception": { "description": "Error: no mod here" },
+                                    },
+                                },
+                            })),
+                            Evaluation::Closes => socket.send_frame(0x8, &[]),
+                            Evaluation::SendsTooMuch => socket
+                                .stream
+                                .write_all(&[
+                                    0x81, 127, 0x7F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+                                ])
+                                .map_err(|e| inspector_error(e.to_string())),
+                        }
+                        .unwrap();
+                    }
+                    "Debugger.resume" => {
+                        socket.send(&json!({ "id": id, "result": {} })).unwrap();
+                    }
+                    method => panic!("unexpected call to {method}"),
+                }
+            }
+        });
+
+        (address, server)
+    }
+
+    fn inject_into(evaluation: Evaluation) -> (Result<()>, String) {
+        let (address, server) = mock_inspector(evaluation);
+        let result = inject(address, std::process::id(), "require(\"/tmp/mod.js\");");
+
+        (result, server.join().unwrap())
+    }
+
+    fn inspector_reason(result: Result<()>) -> String {
+        match result {
+            Err(Error::Inspector(reason)) => reason,
+            _ => panic!("expected an inspector error"),
+        }
+    }
+
+    #[test]
+    fn inject_evaluates_where_the_app_paused() {
+        let (result, expression) = inject_into(Evaluation::Returns);
+
+        assert!(result.is_ok());
+        assert_eq!(expression, "require(\"/tmp/mod.js\");");
+    }
+
+    #[test]
+    fn inject_reports_exceptions() {
+        let (result, _) = inject_into(Evaluation::Throws);
+
+        assert_eq!(
+            inspector_reason(result),
+            "template threw: Error: no mod here"
+        );
+    }
+
+    #[test]
+    fn inject_reports_closed_connections() {
+        let (result, _) = inject_into(Evaluation::Closes);
+
+        assert_eq!(
+            inspector_reason(result),
+            "the inspector closed the connection"
+        );
+    }
+
+    #[test]
+    fn inject_refuses_huge_messages() {
+        let (result, _) = inject_into(Evaluation::SendsTooMuch);
+
+        assert!(inspector_reason(result).starts_with("message larger than"));
+    }
+
+    /// An HTTP server that answers one request with `response`.
+    fn mock_http(response: Vec<u8>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&mut BufReader::new(stream.try_clone().unwrap()));
+
+            // The client may hang up before reading all of it.
+            let _ = stream.write_all(&response);
+        });
+
+        address
+    }
+
+    #[test]
+    fn debugger_url_limits_the_body() {
+        let targets = br#"[{"webSocketDebuggerUrl":"ws://127.0.0.1:9229/a"}]"#;
+
+        let mut response = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+        response.extend(targets);
+        assert_eq!(
+            debugger_url(mock_http(response)).unwrap(),
+            "ws://127.0.0.1:9229/a"
+        );
+
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+            MAX_MESSAGE + 1
+        );
+        let error = debugger_url(mock_http(response.into_bytes())).unwrap_err();
+        assert!(
+            matches!(error, Error::Inspector(reason) if reason.starts_with("message larger than"))
+        );
+
+        let mut response = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+        response.resize(response.len() + MAX_MESSAGE as usize + 1, b' ');
+        let error = debugger_url(mock_http(response)).unwrap_err();
+        assert!(
+            matches!(error, Error::Inspector(reason) if reason.starts_with("message larger than"))
+        );
+
+        let mut response = b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n".to_vec();
+        response.extend(targets);
+        assert!(debugger_url(mock_http(response)).is_err());
+    }
+
+    #[test]
+    fn app_address_only_finds_the_apps_listener() {
+        use std::os::fd::AsRawFd;
+        use std::os::unix::process::CommandExt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let fd = listener.as_raw_fd();
+
+        assert_eq!(app_address(address, std::process::id()), Some(address));
+
+        // An app whose listener is only open in a process its wrapper forked.
+        let mut app = std::process::Command::new("sh");
+        app.args(["-c", &format!("sleep 30 & exec {fd}>&-; wait")])
+            .process_group(0);
+
+        unsafe {
+            app.pre_exec(move || match libc::fcntl(fd, libc::F_SETFD, 0) {
+                -1 => Err(std::io::Error::last_os_error()),
+                _ => Ok(()),
+            });
+        }
+
+        let mut app = app.spawn().unwrap();
+        let pid = app.id();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while process_tree(pid).len() < 2 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(app_address(([127, 0, 0, 1], 0).into(), pid), Some(address));
+        assert_eq!(app_address(address, pid), Some(address));
+
+        // Something else listening on the port isn't the app.
+        let mut other = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        assert_eq!(app_address(address, other.id()), None);
+
+        unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+        other.kill().unwrap();
+        app.wait().unwrap();
+        other.wait().unwrap();
+
+        drop(listener);
+        assert_eq!(app_address(address, std::process::id()), None);
+    }
+
+    #[test]
+    fn proc_net_ips_are_in_host_order() {
+        let loopback = match cfg!(target_endian = "little") {
+            true => "0100007F",
+            false => "7F000001",
+        };
+        assert_eq!(proc_net_ip(loopback), Some(Ipv4Addr::LOCALHOST.into()));
+
+        let loopback = match cfg!(target_endian = "little") {
+            true => "000000000000000000000000".to_string() + "01000000",
+            false => "000000000000000000000000".to_string() + "00000001",
+        };
+        assert_eq!(proc_net_ip(&loopback), Some(Ipv6Addr::LOCALHOST.into()));
+
+        assert_eq!(proc_net_ip("0100007"), None);
+        assert_eq!(proc_net_ip("0100007G"), None);
+    }
+
+    #[test]
+    fn base64_pads() {
+        assert_eq!(base64(b"electron-hook"), "ZWxlY3Ryb24taG9vaw==");
+        assert_eq!(base64(b"hook"), "aG9vaw==");
+        assert_eq!(base64(b"abc"), "YWJj");
+    }
+}
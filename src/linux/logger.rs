@@ -0,0 +1,465 @@
+//! Rolling the log file of a running instance
+//!
+//! The app writes its output into a pipe, and a small process forked from the launcher copies it into the
+//! log file, starting a new one whenever it reaches [LogRotation::max_size]. It is a separate process so it
+//! outlives detached launchers, and it only makes system calls with buffers on the stack, since the launcher
+//! it is forked from may have other threads.
+
+use std::ffi::CStr;
+use std::fs::File;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::{Error, LogRotation, Result};
+
+/// Room for `{milliseconds}.log` and a nul.
+const NAME_LEN: usize = 32;
+
+/// Start copying a new pipe into `log`, which was just created at `path`. Returns the write end of the pipe.
+pub(crate) fn spawn(path: &Path, log: File, rotation: LogRotation) -> Result<File> {
+    let log_error = |source| Error::Log {
+        path: path.into(),
+        source,
+    };
+
+    let dir = path
+        .parent()
+        .ok_or_else(|| Error::InvalidPath(path.into()))?;
+    let dir = File::open(dir).map_err(log_error)?;
+
+    let file_name = path.file_name().map(OsStrExt::as_bytes).unwrap_or_default();
+
+    if file_name.is_empty() || file_name.len() >= NAME_LEN {
+        return Err(Error::InvalidPath(path.into()));
+    }
+
+    let mut name = [0u8; NAME_LEN];
+    name[..file_name.len()].copy_from_slice(file_name);
+
+    let mut fds = [0; 2];
+
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+        return Err(log_error(std::io::Error::last_os_error()));
+    }
+
+    let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+    match unsafe { libc::fork() } {
+        -1 => return Err(log_error(std::io::Error::last_os_error())),
+        0 => unsafe {
+            // Fork again, so the logger is adopted by init rather than left as a zombie of the launcher.
+            if libc::fork() != 0 {
+                libc::_exit(0);
+            }
+
+            run(
+                read.as_raw_fd(),
+                log.as_raw_fd(),
+                dir.as_raw_fd(),
+                name,
+                rotation,
+            )
+        },
+        child => {
+            let mut status = 0;
+
+            while unsafe { libc::waitpid(child, &mut status, 0) } == -1 && errno() == libc::EINTR {}
+        }
+    }
+
+    Ok(write)
+}
+
+/// The logger process: copy `input` into `log` until every writer has closed it.
+unsafe fn run(
+    input: i32,
+    log: i32,
+    dir: i32,
+    mut name: [u8; NAME_LEN],
+    rotation: LogRotation,
+) -> ! {
+    // Don't go down with the launcher's terminal.
+    libc::setsid();
+
+    // Nothing else the launcher had open should be kept open, least of all the write end of the pipe.
+    let mut keep = [input, log, dir];
+    keep.sort_unstable();
+
+    let mut next = 0;
+
+    for fd in keep {
+        close_range(next, fd - 1);
+        next = fd + 1;
+    }
+
+    close_range(next, i32::MAX);
+
+    prune(dir, &name, rotation);
+
+    let mut size = size_of(log);
+    let mut buf = [0u8; 64 * 1024];
+
+    loop {
+        let read = libc::read(input, buf.as_mut_ptr().cast(), buf.len());
+
+        match read {
+            0 => libc::_exit(0),
+            -1 if errno() == libc::EINTR => continue,
+            -1 => libc::_exit(1),
+            _ => {}
+        }
+
+        write_all(log, &buf[..read as usize]);
+        size += read as u64;
+
+        if size < rotation.max_size {
+            continue;
+        }
+
+        // Keep writing to the full file if a new one can't be created, rather than losing output.
+        if let Some(new) = roll(dir, &mut name) {
+            libc::dup2(new, log);
+            libc::close(new);
+
+            size = 0;
+            prune(dir, &name, rotation);
+        }
+    }
+}
+
+/// Close the file descriptors from `low` to `high`, inclusive.
+unsafe fn close_range(low: i32, high: i32) {
+    if low > high {
+        return;
+    }
+
+    if libc::syscall(libc::SYS_close_range, low as u32, high as u32, 0) == 0 {
+        return;
+    }
+
+    // Kernels before 5.9 don't have close_range.
+    for fd in low..=high.min(1023) {
+        libc::close(fd);
+    }
+}
+
+fn errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+unsafe fn write_all(fd: i32, mut bytes: &[u8]) {
+    while !bytes.is_empty() {
+        match libc::write(fd, bytes.as_ptr().cast(), bytes.len()) {
+            -1 if errno() == libc::EINTR => continue,
+            // E.g. the disk is full. Drop the output, but keep draining the pipe so the app isn't blocked.
+            -1 | 0 => return,
+            written => bytes = &bytes[written as usize..],
+        }
+    }
+}
+
+/// The size of the file `fd` refers to, or 0 if it can't be read.
+unsafe fn size_of(fd: i32) -> u64 {
+    let mut statx = std::mem::zeroed::<libc::statx>();
+
+    let result = libc::syscall(
+        libc::SYS_statx,
+        fd,
+        c"".as_ptr(),
+        libc::AT_EMPTY_PATH,
+        libc::STATX_SIZE,
+        &mut statx,
+    );
+
+    match result {
+        0 => statx.stx_size,
+        _ => 0,
+    }
+}
+
+/// The nul-terminated name in `name`.
+fn name_bytes(name: &[u8; NAME_LEN]) -> &[u8] {
+    let len = name.iter().position(|&byte| byte == 0).unwrap_or(NAME_LEN);
+    &name[..len]
+}
+
+/// Create the next log file in `dir`, after the one called `name`, and store its name in `name`.
+unsafe fn roll(dir: i32, name: &mut [u8; NAME_LEN]) -> Option<i32> {
+    let mut now = std::mem::zeroed::<libc::timespec>();
+    libc::clock_gettime(libc::CLOCK_REALTIME, &mut now);
+
+    let now = now.tv_sec as u64 * 1000 + now.tv_nsec as u64 / 1_000_000;
+
+    // Names are timestamps, so the new one must sort after the current one even if the clock went back.
+    let current = name_bytes(name)
+        .iter()
+        .take_while(|byte| byte.is_ascii_digit())
+        .fold(0u64, |n, &byte| {
+            n.wrapping_mul(10).wrapping_add((byte - b'0') as u64)
+        });
+
+    let mut timestamp = now.max(current + 1);
+
+    for _ in 0..16 {
+        format_name(timestamp, name);
+
+        let fd = libc::syscall(
+            libc::SYS_openat,
+            dir,
+            name.as_ptr(),
+            libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_APPEND | libc::O_CLOEXEC,
+            0o644,
+        );
+
+        match fd {
+            -1 if errno() == libc::EEXIST => timestamp += 1,
+            -1 => return None,
+            fd => return Some(fd as i32),
+        }
+    }
+
+    None
+}
+
+/// Write `{timestamp}.log` and a nul into `name`.
+fn format_name(mut timestamp: u64, name: &mut [u8; NAME_LEN]) {
+    let mut digits = [0u8; 20];
+    let mut len = 0;
+
+    loop {
+        digits[len] = b'0' + (timestamp % 10) as u8;
+        len += 1;
+        timestamp /= 10;
+
+        if timestamp == 0 {
+            break;
+        }
+    }
+
+    for (i, &digit) in digits[..len].iter().rev().enumerate() {
+        name[i] = digit;
+    }
+
+    name[len..len + 5].copy_from_slice(b".log\0");
+}
+
+/// Whether the log file called `a` is older than the one called `b`, comparing their timestamps.
+fn older(a: &[u8], b: &[u8]) -> bool {
+    (a.len(), a) < (b.len(), b)
+}
+
+/// Delete the oldest log files in `dir`, other than `current`, until there are at most [LogRotation::max_files].
+unsafe fn prune(dir: i32, current: &[u8; NAME_LEN], rotation: LogRotation) {
+    let current = name_bytes(current);
+
+    loop {
+        let mut count = 0;
+        let mut oldest = [0u8; NAME_LEN];
+
+        libc::lseek(dir, 0, libc::SEEK_SET);
+
+        // Aligned for the dirent headers.
+        let mut buf = [0u64; 1024];
+
+        loop {
+            let read = libc::syscall(
+                libc::SYS_getdents64,
+                dir,
+                buf.as_mut_ptr(),
+                std::mem::size_of_val(&buf),
+            );
+
+            if read <= 0 {
+                break;
+            }
+
+            let bytes = std::slice::from_raw_parts(buf.as_ptr().cast::<u8>(), read as usize);
+            let mut offset = 0;
+
+            while offset + 19 < bytes.len() {
+                let reclen = u16::from_ne_bytes([bytes[offset + 16], bytes[offset + 17]]) as usize;
+                let record = &bytes[offset..(offset + reclen).min(bytes.len())];
+                offset += reclen.max(1);
+
+                let Ok(entry) = CStr::from_bytes_until_nul(&record[19..]) else {
+                    continue;
+                };
+
+                let name = entry.to_bytes();
+
+                if !name.ends_with(b".log") {
+                    continue;
+                }
+
+                count += 1;
+
+                if name == current {
+                    continue;
+                }
+
+                let oldest_name = name_bytes(&oldest);
+
+                if name.len() < NAME_LEN && (oldest_name.is_empty() || older(name, oldest_name)) {
+                    oldest = [0; NAME_LEN];
+                    oldest[..name.len()].copy_from_slice(name);
+                }
+            }
+        }
+
+        if count <= rotation.max_files {
+            return;
+        }
+
+        if oldest[0] == 0 || libc::unlinkat(dir, oldest.as_ptr().cast(), 0) == -1 {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+    use std::time::{Duration, Instant};
+
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "electron-hook-logger-{}-{name}",
+            std::process::id()
+        ));
+
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn logs(dir: &Path) -> Vec<(String, Vec<u8>)> {
+        let mut logs = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .map(|path| {
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                (name, std::fs::read(&path).unwrap())
+            })
+            .collect::<Vec<_>>();
+
+        logs.sort_by_key(|(name, _)| (name.len(), name.clone()));
+        logs
+    }
+
+    #[test]
+    fn format_name_is_a_timestamp() {
+        let mut name = [0xFF; NAME_LEN];
+
+        format_name(1_700_000_000_123, &mut name);
+        assert_eq!(name_bytes(&name), b"1700000000123.log");
+
+        format_name(0, &mut name);
+        assert_eq!(name_bytes(&name), b"0.log");
+    }
+
+    #[test]
+    fn older_compares_timestamps() {
+        assert!(older(b"999.log", b"1000.log"));
+        assert!(older(b"1000.log", b"1001.log"));
+        assert!(!older(b"1001.log", b"1001.log"));
+        assert!(!older(b"1000.log", b"999.log"));
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_files() {
+        let dir = test_dir("prune");
+
+        for (name, size) in [
+            ("100.log", 4),
+            ("200.log", 4),
+            ("300.log", 4),
+            ("400.log", 0),
+        ] {
+            std::fs::write(dir.join(name), vec![b'x'; size]).unwrap();
+        }
+        std::fs::write(dir.join("notes.txt"), "not a log").unwrap();
+
+        let mut current = [0; NAME_LEN];
+        current[..7].copy_from_slice(b"400.log");
+
+        let rotation = LogRotation {
+            max_size: 1,
+            max_files: 3,
+        };
+
+        let dir_file = File::open(&dir).unwrap();
+        unsafe { prune(dir_file.as_raw_fd(), &current, rotation) };
+
+        let names = logs(&dir)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["200.log", "300.log", "400.log", "notes.txt"]);
+
+        let rotation = LogRotation {
+            max_size: 1,
+            max_files: 2,
+        };
+
+        unsafe { prune(dir_file.as_raw_fd(), &current, rotation) };
+
+        let names = logs(&dir)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["300.log", "400.log", "notes.txt"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn spawn_rolls_over_full_files() {
+        let dir = test_dir("spawn");
+        let path = dir.join("1000.log");
+        let log = File::create(&path).unwrap();
+
+        let rotation = LogRotation {
+            max_size: 10,
+            max_files: 2,
+        };
+
+        let mut pipe = spawn(&path, log, rotation).unwrap();
+
+        for line in [b"first line\n", b"secnd line\n", b"third line\n"] {
+            pipe.write_all(line).unwrap();
+
+            // Give the logger a chance to roll over between lines.
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        drop(pipe);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        let logs = loop {
+            let logs = logs(&dir);
+
+            if logs
+                .first()
+                .is_some_and(|(_, content)| content == b"third line\n")
+                || Instant::now() >= deadline
+            {
+                break logs;
+            }
+
+            std::thread::sleep(Duration::from_millis(20));
+        };
+
+        // Every line fills a file, so only the one with the third line and the empty one after it are kept.
+        let contents = logs
+            .iter()
+            .map(|(_, content)| content.as_slice())
+            .collect::<Vec<_>>();
+        assert_eq!(contents, [b"third line\n".as_slice(), b""]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
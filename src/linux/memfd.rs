@@ -0,0 +1,135 @@
+use std::ffi::CStr;
+use std::io::Write;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+
+/// The name the in-memory ASAR's memfd is created with.
+pub(crate) const ASAR_NAME: &CStr = c"electron-hook.asar";
+
+/// Create a sealed, read-only memfd holding `bytes`.
+///
+/// The fd is close-on-exec, so it only reaches the processes it is passed to with [inherit].
+pub(crate) fn create(name: &CStr, bytes: &[u8]) -> std::io::Result<OwnedFd> {
+    let fd =
+        unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING) };
+
+    if fd == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mut file = std::fs::File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+
+    file.write_all(bytes)?;
+
+    // Nothing else should be able to change the archive under the app.
+    let seals = libc::F_SEAL_SEAL | libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE;
+
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(file.into())
+}
+
+/// Let the child spawned by `target` inherit `fd` at the same number.
+///
+/// `fd` must stay open until the child has been spawned.
+pub(crate) fn inherit(target: &mut std::process::Command, fd: &OwnedFd) {
+    let fd = fd.as_raw_fd();
+
+    // This runs in the forked child, so our own copy stays close-on-exec.
+    unsafe {
+        target.pre_exec(move || {
+            if libc::fcntl(fd, libc::F_SETFD, 0) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// The path a process that inherited `fd` can open it at.
+pub(crate) fn proc_path(fd: &OwnedFd) -> PathBuf {
+    PathBuf::from(format!("/proc/self/fd/{}", fd.as_raw_fd()))
+}
+
+/// Whether `path` opens a memfd created with `name` in this process.
+///
+/// A `/proc/self/fd/N` path is only meaningful in the process that inherited the fd. Its
+/// descendants either don't have fd `N` or have some other file there.
+pub(crate) fn is_memfd(path: &CStr, name: &CStr) -> bool {
+    let mut buf = [0u8; 256];
+    let len = unsafe { libc::readlink(path.as_ptr(), buf.as_mut_ptr().cast(), buf.len()) };
+
+    let Ok(len) = usize::try_from(len) else {
+        return false;
+    };
+
+    // The kernel names these `/memfd:<name> (deleted)`.
+    buf[..len]
+        .strip_prefix(b"/memfd:")
+        .and_then(|rest| rest.strip_prefix(name.to_bytes()))
+        .is_some_and(|rest| rest == b" (deleted)")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::process::{Command, Stdio};
+
+    fn c_path(fd: &OwnedFd) -> CString {
+        CString::new(proc_path(fd).as_os_str().as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn children_read_inherited_memfds() {
+        let fd = create(ASAR_NAME, b"asar bytes").unwrap();
+
+        let mut target = Command::new("cat");
+        target.arg(proc_path(&fd)).stderr(Stdio::null());
+        inherit(&mut target, &fd);
+
+        let output = target.output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"asar bytes");
+
+        // Without `inherit`, the fd is closed on exec.
+        let output = Command::new("cat")
+            .arg(proc_path(&fd))
+            .stderr(Stdio::null())
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn memfds_are_sealed() {
+        let fd = create(ASAR_NAME, b"asar bytes").unwrap();
+
+        assert!(std::fs::OpenOptions::new()
+            .write(true)
+            .open(proc_path(&fd))
+            .and_then(|mut file| file.write_all(b"other"))
+            .is_err());
+        assert_eq!(std::fs::read(proc_path(&fd)).unwrap(), b"asar bytes");
+    }
+
+    #[test]
+    fn is_memfd_checks_the_fd_behind_the_path() {
+        let fd = create(ASAR_NAME, b"").unwrap();
+        assert!(is_memfd(&c_path(&fd), ASAR_NAME));
+        assert!(!is_memfd(&c_path(&fd), c"electron-hook"));
+
+        let other = create(c"other.asar", b"").unwrap();
+        assert!(!is_memfd(&c_path(&other), ASAR_NAME));
+
+        let file: OwnedFd = std::fs::File::open("/dev/null").unwrap().into();
+        assert!(!is_memfd(&c_path(&file), ASAR_NAME));
+
+        assert!(!is_memfd(c"/proc/self/fd/999999", ASAR_NAME));
+    }
+}
@@ -1,161 +1,658 @@
+mod appimage;
+pub mod discovery;
+pub mod extension;
+pub(crate) mod flatpak;
 mod hooks;
+mod inspector;
+mod logger;
+mod memfd;
+mod node_options;
+mod preload;
+pub(crate) mod resolve;
+pub mod sandbox;
+mod snap;
+mod trace;
 
-use super::FlatpakID;
-
-pub(crate) fn launch_flatpak(
-    id: &FlatpakID,
-    library_path: &str,
-    asar_path: &str,
-    args: Vec<String>,
-    detach: bool,
-) -> Result<Option<u32>, String> {
-    // If the library starts with /home, return the whole path.
-    // If the library is absolute, prefix wtih /run/host
-    // If it's a local file, provide the whole path
-    // Otherwise, assume it's in /usr/lib, so prefix with /run/host/usr/lib
-    let library_path = if library_path.starts_with("/home") {
-        library_path.to_string()
-    } else if library_path.starts_with('/') {
-        format!("/run/host{}", library_path)
-    } else {
-        let current_dir = std::env::current_dir().unwrap();
-        let local_path = current_dir.join(&library_path);
-
-        if local_path.is_file() {
-            local_path.to_string_lossy().into_owned()
-        } else {
-            format!("/run/host/usr/lib/{}", library_path)
+use std::ffi::OsString;
+use std::io::Write;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+
+use super::{
+    Error, FlatpakID, FlatpakPermission, FlatpakTarget, Injection, Instance, Launcher, Result,
+    StdioPolicy,
+};
+
+use sandbox::{Access, FlatpakInfo, SandboxPaths};
+
+/// Translate a path we can see into the path the host sees it at.
+///
+/// This only changes anything if we are running inside a Flatpak ourselves. Files that only exist inside
+/// our sandbox are copied into the electron-hook cache first, which the host can see.
+fn host_path(path: &Path) -> Result<PathBuf> {
+    let Some(info) = FlatpakInfo::current() else {
+        return Ok(path.to_path_buf());
+    };
+
+    if let Some(host_path) = info.host_path(path) {
+        return Ok(host_path);
+    }
+
+    let staged = flatpak::stage(path)?;
+
+    info.host_path(&staged)
+        .ok_or_else(|| Error::SandboxUnreachable(path.into()))
+}
+
+/// Run `target` on the host through `flatpak-spawn --host` if we are running inside a Flatpak ourselves.
+///
+/// `flatpak-spawn` doesn't pass our environment on, so the environment of `target` and the `MODLOADER_*`
+/// variables set by [Asar](crate::asar::Asar) are forwarded with `--env`, and `forward_fd` with `--forward-fd`.
+fn host_command(
+    target: std::process::Command,
+    forward_fd: Option<&OwnedFd>,
+) -> std::process::Command {
+    let Some(info) = FlatpakInfo::current() else {
+        return target;
+    };
+
+    let env_arg = |key: &std::ffi::OsStr, value: &std::ffi::OsStr| {
+        let mut arg = OsString::from("--env=");
+        arg.push(key);
+        arg.push("=");
+        arg.push(value);
+        arg
+    };
+
+    let mut host = std::process::Command::new("flatpak-spawn");
+
+    host.arg("--host");
+
+    if let Some(working_dir) = target.get_current_dir() {
+        host.arg(format!("--directory={}", working_dir.display()));
+    }
+
+    for (key, value) in std::env::vars_os() {
+        let inherited = key.to_string_lossy().starts_with("MODLOADER_")
+            && target.get_envs().all(|(explicit, _)| explicit != key);
+
+        if !inherited {
+            continue;
+        }
+
+        let value = match info.host_path(Path::new(&value)) {
+            Some(host_path) => host_path.into_os_string(),
+            None => value,
+        };
+
+        host.arg(env_arg(&key, &value));
+    }
+
+    for (key, value) in target.get_envs() {
+        match value {
+            Some(value) => host.arg(env_arg(key, value)),
+            None => host.arg(format!("--unset-env={}", key.to_string_lossy())),
+        };
+    }
+
+    if let Some(fd) = forward_fd {
+        host.arg(format!("--forward-fd={}", fd.as_raw_fd()));
+    }
+
+    host.arg(target.get_program()).args(target.get_args());
+
+    host
+}
+
+/// The program and arguments of `target` for the log.
+///
+/// Logs end up in bug reports, so the values of environment variables passed as arguments, like
+/// `flatpak run --env=` or the exports in the snap shell script, are left out. They may hold tokens.
+fn describe(target: &std::process::Command) -> String {
+    let hide_value = |arg: &str, prefix: &str| {
+        let (key, _) = arg.strip_prefix(prefix)?.split_once('=')?;
+        Some(format!("{prefix}{key}=…"))
+    };
+
+    std::iter::once(target.get_program())
+        .chain(target.get_args())
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+
+            let arg = match hide_value(&arg, "--env=") {
+                Some(arg) => arg,
+                None => arg
+                    .lines()
+                    .map(|line| hide_value(line, "export ").unwrap_or_else(|| line.to_string()))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            };
+
+            format!("{arg:?}")
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn apply_stdio(target: &mut std::process::Command, launcher: &Launcher) -> Result<()> {
+    match launcher.stdio_policy() {
+        StdioPolicy::Inherit => {}
+        // We also need to detach stdin.
+        StdioPolicy::Null => {
+            target
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .stdin(std::process::Stdio::null());
+        }
+        StdioPolicy::Log(rotation) => {
+            let (path, mut log) = crate::logs::create(&launcher.asar_id())?;
+
+            // Record what was launched, so bug reports carry some context.
+            let _ = writeln!(log, "[electron-hook] {}", describe(target));
+
+            let stdout = logger::spawn(&path, log, rotation)?;
+            let stderr = stdout
+                .try_clone()
+                .map_err(|source| Error::Log { path, source })?;
+
+            target
+                .stdout(stdout)
+                .stderr(stderr)
+                .stdin(std::process::Stdio::null());
         }
     };
 
-    let asar_dir = std::path::PathBuf::from(asar_path);
-    let asar_dir = asar_dir
-        .parent()
-        .ok_or("Failed to get parent directory from asar path")?
-        .to_string_lossy();
+    Ok(())
+}
+
+fn apply_detach(target: &mut std::process::Command, launcher: &Launcher) {
+    if !launcher.detach {
+        return;
+    }
 
-    let mod_entrypoint = std::env::var("MODLOADER_MOD_ENTRYPOINT")
-        .map_err(|_| "MODLOADER_MOD_ENTRYPOINT not set")?;
+    // Move the child into its own session, so it has no controlling terminal and doesn't die when the
+    // parent process (e.g. terminal) is closed. This runs in the forked child, so the launcher's own
+    // session is left untouched. The child is never a process group leader here, so setsid() can't fail
+    // for that reason, but we still report any error instead of ignoring it.
+    unsafe {
+        target.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Chromium only isolates its profile when told to, so pass the profile directory on as `--user-data-dir`.
+///
+/// This is skipped if the caller already passes their own `--user-data-dir`.
+fn user_data_dir_arg(launcher: &Launcher, profile_dir: &str) -> Option<String> {
+    let user_data_dir = "--user-data-dir";
+
+    let overridden = launcher
+        .args
+        .iter()
+        .any(|arg| arg == user_data_dir || arg.starts_with(&format!("{user_data_dir}=")));
+
+    (!overridden).then(|| format!("{user_data_dir}={profile_dir}"))
+}
 
-    let mod_entrypoint_dir = std::path::PathBuf::from(mod_entrypoint);
-    let mod_entrypoint_dir = mod_entrypoint_dir
-        .parent()
-        .ok_or("Failed to get parent directory from mod entrypoint")?
-        .to_string_lossy();
+/// The redirections for `MODLOADER_REDIRECTS`. The hooks add the default ones themselves.
+fn redirects(launcher: &Launcher) -> String {
+    crate::redirect::to_json(&launcher.redirects)
+}
+
+/// The in-memory ASAR as a memfd for the app to inherit, if there is one.
+fn asar_memfd(launcher: &Launcher) -> Result<Option<OwnedFd>> {
+    launcher
+        .asar_bytes
+        .as_deref()
+        .map(|bytes| memfd::create(memfd::ASAR_NAME, bytes).map_err(Error::AsarMemory))
+        .transpose()
+}
+
+/// Spawn `target`, handing it the in-memory ASAR if there is one.
+fn spawn_with(mut target: std::process::Command, asar_memfd: Option<&OwnedFd>) -> Result<Instance> {
+    if let Some(fd) = asar_memfd {
+        memfd::inherit(&mut target, fd);
+    }
+
+    spawn(target)
+}
+
+fn spawn(mut target: std::process::Command) -> Result<Instance> {
+    let target = target.spawn().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => Error::ExecutableNotFound(target.get_program().into()),
+        _ => Error::Spawn(e),
+    })?;
+
+    Ok(Instance::from_child(target))
+}
 
-    let current_executable = std::env::current_exe().unwrap().display().to_string();
+pub(crate) fn launch_flatpak(launcher: &Launcher, flatpak: &FlatpakTarget) -> Result<Instance> {
+    let mut sandbox = SandboxPaths::new();
 
-    let current_executable = if current_executable.starts_with("/usr") {
-        format!("/run/host{}", current_executable)
-    } else {
-        current_executable
+    let library_path = flatpak::host_library_path(&launcher.library_path)?;
+    let library_path = flatpak::expose_file(&mut sandbox, &host_path(&library_path)?)?;
+
+    // An in-memory ASAR needs no filesystem access at all.
+    let asar_memfd = asar_memfd(launcher)?;
+    let asar_path = match &asar_memfd {
+        Some(fd) => memfd::proc_path(fd),
+        None => flatpak::expose_in_dir(&mut sandbox, &host_path(Path::new(&launcher.asar_path))?)?,
+    };
+
+    let mod_entrypoint =
+        std::env::var("MODLOADER_MOD_ENTRYPOINT").map_err(|_| Error::MissingModEntrypoint)?;
+
+    // let the mod update itself...
+    let mod_entrypoint = flatpak::expose_in_dir_with(
+        &mut sandbox,
+        &host_path(Path::new(&mod_entrypoint))?,
+        Access::Create,
+    )?;
+
+    let current_executable = std::env::current_exe().map_err(Error::CurrentExe)?;
+    let current_executable = host_path(&current_executable)?;
+    let current_executable = sandbox
+        .expose(&current_executable, Access::ReadOnly)
+        .unwrap_or(current_executable);
+
+    let profile_dir = launcher.profile_dir();
+
+    let profile_dir = match profile_dir {
+        Some(profile_dir) => {
+            let profile_dir = crate::paths::ensure_dir(profile_dir.into())?;
+            Some(flatpak::expose_dir(
+                &mut sandbox,
+                &host_path(&profile_dir)?,
+                Access::Create,
+            )?)
+        }
+        None => None,
     };
 
     let mut target = std::process::Command::new("flatpak");
 
     target.arg("run");
 
-    match id {
+    match &flatpak.id {
         FlatpakID::User(_) => target.arg("--user"),
         FlatpakID::System(_) => target.arg("--system"),
     };
 
+    if let Some(branch) = &flatpak.branch {
+        target.arg(format!("--branch={branch}"));
+    }
+
+    if let Some(arch) = &flatpak.arch {
+        target.arg(format!("--arch={arch}"));
+    }
+
+    if let Some(command) = &flatpak.command {
+        target.arg(format!("--command={command}"));
+    }
+
+    if let Some(working_dir) = &launcher.working_dir {
+        target.arg(format!("--cwd={}", working_dir.display()));
+    }
+
     target
-        .arg("--filesystem=host:ro") // allows us to read /usr/lib as /run/host/usr/lib
-        .arg(format!("--filesystem={}:ro", asar_dir)) // Read-only access to the ASAR dir
-        .arg(format!("--filesystem={}:create", mod_entrypoint_dir)) // let the mod update itself...
-        .arg(format!("--filesystem={}:ro", current_executable))
-        .arg(format!("--env=ZYPAK_LD_PRELOAD={}", library_path)) // give zypak our LD_PRELOAD
-        .arg(format!("--env=MODLOADER_ASAR_PATH={}", asar_path))
-        .arg(format!("--env=MODLOADER_EXECUTABLE={}", current_executable))
-        .arg(format!("--env=MODLOADER_LIBRARY_PATH={}", library_path))
+        .args(sandbox.filesystem_args())
+        .arg(format!("--env=ZYPAK_LD_PRELOAD={}", library_path.display())) // give zypak our LD_PRELOAD
+        .arg(format!("--env=MODLOADER_ASAR_PATH={}", asar_path.display()))
         .arg(format!(
-            "--env=MODLOADER_ORIGINAL_ASAR_RELATIVE=../_app.asar"
+            "--env=MODLOADER_EXECUTABLE={}",
+            current_executable.display()
         ))
-        .arg(id.to_string())
-        .args(args);
+        .arg(format!(
+            "--env=MODLOADER_LIBRARY_PATH={}",
+            library_path.display()
+        ))
+        .arg(format!(
+            "--env=MODLOADER_MOD_ENTRYPOINT={}",
+            mod_entrypoint.display()
+        ))
+        .arg("--env=MODLOADER_INJECTION=preload")
+        .arg("--env=MODLOADER_ORIGINAL_ASAR_RELATIVE=../_app.asar")
+        .arg(format!("--env=MODLOADER_REDIRECTS={}", redirects(launcher)))
+        .arg(format!(
+            "--env=MODLOADER_PRELOAD_POLICY={}",
+            launcher.preload_policy.as_str()
+        ));
 
-    // We also need to detach stdin.
-    if detach {
-        target
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .stdin(std::process::Stdio::null());
+    // Without this, the hooks fall back to redirecting any `resources/app.asar`.
+    if let Some(resources_dir) = flatpak::resources_dir(flatpak) {
+        target.arg(format!(
+            "--env=MODLOADER_RESOURCES_DIR={}",
+            resources_dir.display()
+        ));
+    }
+
+    if let Some(profile_dir) = &profile_dir {
+        target.arg(format!(
+            "--env=MODLOADER_PROFILE_DIR={}",
+            profile_dir.display()
+        ));
+    }
+
+    target.args(flatpak.permissions.iter().map(FlatpakPermission::to_arg));
+
+    for (key, value) in &launcher.env {
+        target.arg(format!("--env={key}={value}"));
+    }
+
+    target.arg(flatpak.id.to_string());
+
+    if let Some(profile_dir) = &profile_dir {
+        target.args(user_data_dir_arg(launcher, &profile_dir.to_string_lossy()));
+    }
+
+    target.args(&launcher.args);
+
+    let mut target = host_command(target, asar_memfd.as_ref());
+
+    apply_stdio(&mut target, launcher)?;
+    apply_detach(&mut target, launcher);
+
+    spawn_with(target, asar_memfd.as_ref())
+}
+
+pub(crate) fn launch_appimage(launcher: &Launcher, appimage: &Path) -> Result<Instance> {
+    if !appimage.is_file() {
+        return Err(Error::ExecutableNotFound(appimage.into()));
+    }
+
+    if appimage::fuse_available() {
+        return launch(launcher, appimage);
+    }
+
+    let app_dir = appimage::extract(appimage)?;
+
+    // The AppImage runtime would normally set these for AppRun.
+    let mut launcher = launcher.clone();
+    launcher.env.splice(
+        0..0,
+        [
+            ("APPDIR".into(), app_dir.to_string_lossy().into_owned()),
+            ("APPIMAGE".into(), appimage.to_string_lossy().into_owned()),
+        ],
+    );
+
+    launch(&launcher, &app_dir.join("AppRun"))
+}
+
+pub(crate) fn launch_snap(launcher: &Launcher, name: &str) -> Result<Instance> {
+    let snap = snap::Snap::find(name)?;
+
+    let library_path = flatpak::host_library_path(&launcher.library_path)?;
+    let library_path = snap.expose_file(&library_path)?;
+
+    let asar_memfd = asar_memfd(launcher)?;
+    let asar_path = match &asar_memfd {
+        Some(fd) => memfd::proc_path(fd),
+        None => snap.expose_file(Path::new(&launcher.asar_path))?,
     };
 
-    let Ok(mut target) = target.spawn() else {
-        return Err("Failed to launch instance".into());
+    let mod_entrypoint =
+        std::env::var("MODLOADER_MOD_ENTRYPOINT").map_err(|_| Error::MissingModEntrypoint)?;
+    let mod_entrypoint = snap.expose_in_dir(Path::new(&mod_entrypoint))?;
+
+    let current_executable = std::env::current_exe().map_err(Error::CurrentExe)?;
+
+    let process_args = std::env::args().skip(1).collect::<Vec<String>>();
+    let process_args_json = serde_json::to_string(&process_args).unwrap_or_else(|_| "[]".into());
+
+    let mut env = vec![
+        (
+            "MODLOADER_ASAR_PATH",
+            asar_path.to_string_lossy().into_owned(),
+        ),
+        (
+            "MODLOADER_EXECUTABLE",
+            current_executable.to_string_lossy().into_owned(),
+        ),
+        (
+            "MODLOADER_LIBRARY_PATH",
+            library_path.to_string_lossy().into_owned(),
+        ),
+        (
+            "MODLOADER_MOD_ENTRYPOINT",
+            mod_entrypoint.to_string_lossy().into_owned(),
+        ),
+        ("MODLOADER_ORIGINAL_ASAR_RELATIVE", "../_app.asar".into()),
+        ("MODLOADER_PROCESS_ARGV", process_args_json),
+        ("MODLOADER_INJECTION", "preload".into()),
+        ("MODLOADER_REDIRECTS", redirects(launcher)),
+        (
+            "MODLOADER_PRELOAD_POLICY",
+            launcher.preload_policy.as_str().into(),
+        ),
+        (
+            "MODLOADER_RESOURCES_DIR",
+            snap.resources_dir().to_string_lossy().into_owned(),
+        ),
+    ];
+
+    let mut args = Vec::new();
+
+    if let Some(profile_dir) = launcher.profile_dir() {
+        let profile_dir = crate::paths::ensure_dir(profile_dir.into())?;
+        let profile_dir = snap
+            .expose_dir(&profile_dir)?
+            .to_string_lossy()
+            .into_owned();
+
+        args.extend(user_data_dir_arg(launcher, &profile_dir));
+        env.push(("MODLOADER_PROFILE_DIR", profile_dir));
+    }
+
+    env.extend(
+        launcher
+            .env
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.clone())),
+    );
+
+    args.extend(launcher.args.iter().cloned());
+
+    let working_dir = match &launcher.working_dir {
+        Some(working_dir) => snap::shell_quote(&working_dir.to_string_lossy()),
+        None => match snap.executable.parent() {
+            Some(dir) => format!("\"$SNAP\"/{}", snap::shell_quote(&dir.to_string_lossy())),
+            None => "\"$SNAP\"".into(),
+        },
     };
 
-    // If we aren't detaching, keep the process alive.
-    if !detach {
-        let Ok(_) = target.wait() else {
-            return Err("Process exited unexpectedly".into());
-        };
+    // snap-confine is setuid, so the dynamic linker drops LD_PRELOAD on the way in. Set the environment
+    // from a shell inside the confinement instead, and exec the app from there. Anything else preloaded in
+    // there is kept, like for the other targets.
+    let mut script = format!(
+        "export LD_PRELOAD=\"${{LD_PRELOAD:+$LD_PRELOAD:}}\"{}\n",
+        snap::shell_quote(&library_path.to_string_lossy())
+    );
 
-        return Ok(None);
+    for (key, value) in &env {
+        script.push_str(&format!("export {key}={}\n", snap::shell_quote(value)));
     }
 
-    let pid = target.id();
+    script.push_str(&format!(
+        "cd {working_dir} && exec \"$SNAP\"/{} \"$@\"",
+        snap::shell_quote(&snap.executable.to_string_lossy())
+    ));
 
-    Ok(Some(pid))
+    let mut target = std::process::Command::new("snap");
+
+    target
+        .args(["run", "--shell", &snap.name, "-c", &script, "electron-hook"])
+        .args(args);
+
+    apply_stdio(&mut target, launcher)?;
+    apply_detach(&mut target, launcher);
+
+    spawn_with(target, asar_memfd.as_ref())
 }
 
-pub(crate) fn launch(
-    executable: &str,
-    library_path: &str,
-    asar_path: &str,
-    args: Vec<String>,
-    detach: bool,
-) -> Result<Option<u32>, String> {
-    let executable = std::path::PathBuf::from(executable);
+pub(crate) fn launch(launcher: &Launcher, executable: &Path) -> Result<Instance> {
+    // From inside a Flatpak, the executable and working directory are host paths, which we usually can't
+    // see, so they are passed on as they are. Only our own files need translating with `host_path`.
+    let sandboxed = FlatpakInfo::current().is_some();
 
-    // Detach the process from the parent. This prevents the application from dying when the parent process (e.g. terminal) is closed.
-    if detach {
-        unsafe { libc::setsid() };
+    if !sandboxed && !executable.is_file() {
+        return Err(Error::ExecutableNotFound(executable.into()));
     }
 
-    let working_dir = executable
-        .parent()
-        .ok_or("Failed to get parent directory from executable")?;
+    // Launch the real binary rather than a symlink or wrapper script, so the working directory is next to
+    // its resources, keeping whatever the wrapper would have passed to it.
+    let resolved = match sandboxed {
+        true => resolve::Resolved::as_is(executable),
+        false => resolve::resolve(executable),
+    };
+    let executable = &resolved.executable;
+
+    let working_dir = match &launcher.working_dir {
+        Some(working_dir) => working_dir.as_path(),
+        None => executable
+            .parent()
+            .ok_or_else(|| Error::InvalidPath(executable.into()))?,
+    };
+
+    let current_executable = std::env::current_exe().map_err(Error::CurrentExe)?;
+    let current_executable = host_path(&current_executable)?;
+
+    // Electron finds its resources through /proc/self/exe, so the hooks will see the canonical path.
+    let resources_dir = resolved
+        .resources_dir
+        .and_then(|resources_dir| resources_dir.canonicalize().ok());
+
+    let library_path = match sandboxed {
+        true => host_path(&flatpak::host_library_path(&launcher.library_path)?)?,
+        false => launcher.library_path.as_str().into(),
+    };
+    let asar_memfd = asar_memfd(launcher)?;
+    let asar_path = match &asar_memfd {
+        Some(fd) => memfd::proc_path(fd),
+        None => host_path(Path::new(&launcher.asar_path))?,
+    };
+
+    // Keep whatever else the user preloads or passes to Node.js, like overlays or allocators. From inside a
+    // Flatpak, our own environment is meaningless on the host.
+    let inherited = |key: &str| match sandboxed {
+        true => None,
+        false => std::env::var_os(key),
+    };
+
+    let require_script = match launcher.injection {
+        Injection::NodeOptions => {
+            node_options::require_script(launcher, &resolved.executable, resources_dir.as_deref())?
+                .map(|script| host_path(&script))
+                .transpose()?
+        }
+        Injection::Preload | Injection::Inspector(_) => None,
+    };
+
+    let inspect_address = match launcher.injection {
+        Injection::Inspector(address) if inspector::allowed(&resolved.executable) => Some(address),
+        _ => None,
+    };
+
+    let injection_env = match (&require_script, inspect_address) {
+        (Some(script), _) => vec![
+            (
+                "NODE_OPTIONS",
+                node_options::node_options(inherited("NODE_OPTIONS").as_deref(), script),
+            ),
+            ("MODLOADER_INJECTION", "node-options".into()),
+        ],
+        (None, Some(_)) => vec![("MODLOADER_INJECTION", "inspector".into())],
+        (None, None) => vec![
+            (
+                "LD_PRELOAD",
+                preload::merge(inherited("LD_PRELOAD").as_deref(), library_path.as_os_str()),
+            ),
+            ("MODLOADER_INJECTION", "preload".into()),
+        ],
+    };
 
-    let mut target = std::process::Command::new(&executable);
+    let mut target = std::process::Command::new(executable);
 
     let process_args = std::env::args().skip(1).collect::<Vec<String>>();
     let process_args_json = serde_json::to_string(&process_args).unwrap_or_else(|_| "[]".into());
 
     target
         .current_dir(working_dir)
-        .env("LD_PRELOAD", library_path)
-        .env("MODLOADER_ASAR_PATH", asar_path)
-        .env("MODLOADER_EXECUTABLE", std::env::current_exe().unwrap())
-        .env("MODLOADER_LIBRARY_PATH", library_path)
+        .envs(resolved.env)
+        .envs(injection_env)
+        .env("MODLOADER_ASAR_PATH", &asar_path)
+        .env("MODLOADER_EXECUTABLE", current_executable)
+        .env("MODLOADER_LIBRARY_PATH", &library_path)
         .env("MODLOADER_ORIGINAL_ASAR_RELATIVE", "../_app.asar")
         .env("MODLOADER_PROCESS_ARGV", process_args_json)
-        .args(args);
+        .env("MODLOADER_REDIRECTS", redirects(launcher))
+        .env("MODLOADER_PRELOAD_POLICY", launcher.preload_policy.as_str())
+        .args(inspect_address.map(|address| format!("--inspect-brk={address}")))
+        .args(resolved.args);
+
+    if let Some(resources_dir) = resources_dir {
+        target.env("MODLOADER_RESOURCES_DIR", resources_dir);
+    }
+
+    if let Some(profile_dir) = launcher.profile_dir() {
+        let profile_dir = host_path(&crate::paths::ensure_dir(profile_dir.into())?)?;
 
-    // We also need to detach stdin.
-    if detach {
         target
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .stdin(std::process::Stdio::null());
-    };
+            .env("MODLOADER_PROFILE_DIR", &profile_dir)
+            .args(user_data_dir_arg(launcher, &profile_dir.to_string_lossy()));
+    }
 
-    let Ok(mut target) = target.spawn() else {
-        return Err("Failed to launch instance".into());
-    };
+    target
+        .envs(launcher.env.iter().map(|(key, value)| (key, value)))
+        .args(&launcher.args);
 
-    // If we aren't detaching, keep the process alive.
-    if !detach {
-        let Ok(_) = target.wait() else {
-            return Err("Process exited unexpectedly".into());
-        };
+    let mut target = host_command(target, asar_memfd.as_ref());
+
+    apply_stdio(&mut target, launcher)?;
+    apply_detach(&mut target, launcher);
+
+    let mut instance = spawn_with(target, asar_memfd.as_ref())?;
 
-        return Ok(None);
+    if let Some(address) = inspect_address {
+        if let Err(e) = inspector::inject(address, instance.pid(), &inspector::expression(launcher))
+        {
+            // Otherwise the app would wait for a debugger forever.
+            let _ = instance.kill();
+            return Err(e);
+        }
     }
 
-    let pid = target.id();
+    Ok(instance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_leaves_out_environment_values() {
+        let mut target = std::process::Command::new("flatpak");
+        target
+            .env("DISCORD_TOKEN", "secret")
+            .args(["run", "--env=DISCORD_TOKEN=secret", "--user"])
+            .args([
+                "-c",
+                "export LD_PRELOAD='/tmp/lib.so'\nexport TOKEN='secret'\nexec app",
+            ])
+            .arg("com.discordapp.Discord");
 
-    Ok(Some(pid))
+        let description = describe(&target);
+
+        assert!(!description.contains("secret"));
+        assert_eq!(
+            description,
+            "\"flatpak\" \"run\" \"--env=DISCORD_TOKEN=…\" \"--user\" \"-c\" \
+             \"export LD_PRELOAD=…\\nexport TOKEN=…\\nexec app\" \"com.discordapp.Discord\""
+        );
+    }
 }
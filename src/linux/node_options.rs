@@ -0,0 +1,127 @@
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use crate::fuses::{Fuse, Fuses};
+use crate::{Error, Launcher, Result};
+
+/// Write the ASAR template as a script for `--require`, if the Electron binary at `executable` honours
+/// `NODE_OPTIONS`.
+///
+/// Returns `None` if it doesn't, or if its fuses or the template can't be read, so the caller can fall back
+/// to preloading.
+pub(crate) fn require_script(
+    launcher: &Launcher,
+    executable: &Path,
+    resources_dir: Option<&Path>,
+) -> Result<Option<PathBuf>> {
+    let allowed = Fuses::read(executable)
+        .ok()
+        .and_then(|fuses| fuses.get(Fuse::EnableNodeOptionsEnvironmentVariable))
+        .unwrap_or(false);
+
+    if !allowed || packaged(executable, resources_dir) {
+        return Ok(None);
+    }
+
+    let Some(template) = template(launcher) else {
+        return Ok(None);
+    };
+
+    let path = crate::paths::require_script_path(&launcher.asar_id())?;
+
+    // NODE_OPTIONS reaches every Node.js process the app starts, but the template is meant for the main one.
+    let mut script =
+        b"// Generated by electron-hook\nif (process.type === \"browser\") {\n".to_vec();
+    script.extend(template);
+    script.extend(b"\n}\n");
+
+    std::fs::write(&path, script).map_err(|source| Error::AsarWrite {
+        path: path.clone(),
+        source: Box::new(source),
+    })?;
+
+    Ok(Some(path))
+}
+
+/// Whether Electron treats the app at `executable` as packaged, in which case it ignores `NODE_OPTIONS`
+/// apart from `--max-http-header-size` and `--http-parser`.
+///
+/// Electron only honours the rest when it runs as `electron` itself, e.g. `electron path/to/app`. An
+/// `app.asar` in the resources directory means the app is packaged whatever the executable is called.
+fn packaged(executable: &Path, resources_dir: Option<&Path>) -> bool {
+    let is_electron = executable
+        .file_name()
+        .is_some_and(|name| name.eq_ignore_ascii_case("electron"));
+
+    let resources_dir = match resources_dir {
+        Some(resources_dir) => resources_dir.to_path_buf(),
+        None => executable.with_file_name("resources"),
+    };
+
+    !is_electron || resources_dir.join("app.asar").exists()
+}
+
+/// The `index.js` of the ASAR to inject.
+#[cfg(feature = "asar")]
+pub(crate) fn template(launcher: &Launcher) -> Option<Vec<u8>> {
+    match &launcher.asar_bytes {
+        Some(archive) => crate::asar::index_js(archive),
+        None => crate::asar::index_js(&std::fs::read(&launcher.asar_path).ok()?),
+    }
+}
+
+/// Reading ASARs needs the `asar` feature.
+#[cfg(not(feature = "asar"))]
+pub(crate) fn template(_launcher: &Launcher) -> Option<Vec<u8>> {
+    None
+}
+
+/// `existing` NODE_OPTIONS with `--require <script>` in front.
+pub(crate) fn node_options(existing: Option<&OsStr>, script: &Path) -> OsString {
+    // Node.js splits NODE_OPTIONS on spaces, unless they are inside double quotes.
+    let mut options = b"--require \"".to_vec();
+
+    for &byte in script.as_os_str().as_bytes() {
+        if byte == b'"' || byte == b'\\' {
+            options.push(b'\\');
+        }
+        options.push(byte);
+    }
+
+    options.push(b'"');
+
+    if let Some(existing) = existing.filter(|existing| !existing.is_empty()) {
+        options.push(b' ');
+        options.extend(existing.as_bytes());
+    }
+
+    OsString::from_vec(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packaged_apps_fall_back() {
+        let dir =
+            std::env::temp_dir().join(format!("electron-hook-node-options-{}", std::process::id()));
+        let resources_dir = dir.join("resources");
+        std::fs::create_dir_all(&resources_dir).unwrap();
+
+        // `electron path/to/app`
+        assert!(!packaged(&dir.join("electron"), None));
+        assert!(!packaged(&dir.join("Electron"), Some(&resources_dir)));
+
+        // Apps renamed from `electron`, like most of them are.
+        assert!(packaged(&dir.join("Discord"), None));
+        assert!(packaged(&dir.join("electron-app"), Some(&resources_dir)));
+
+        std::fs::write(resources_dir.join("app.asar"), b"").unwrap();
+        assert!(packaged(&dir.join("electron"), None));
+        assert!(packaged(&dir.join("electron"), Some(&resources_dir)));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,480 @@
+//! Which processes started by the app keep the hook library preloaded, and editing `LD_PRELOAD`
+//!
+//! Most of this runs in the hooks, often in a freshly forked child of a multithreaded process, so only
+//! [merge] may allocate.
+
+use std::ffi::{c_char, c_void, CStr, OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use crate::PreloadPolicy;
+
+const UNKNOWN: u8 = 0;
+const BROWSER_ONLY: u8 = 1;
+const ELECTRON: u8 = 2;
+const ALL: u8 = 3;
+
+static POLICY: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+/// Whether this process is an Electron executable, rather than a wrapper or runtime that starts one.
+static IN_ELECTRON: AtomicBool = AtomicBool::new(false);
+
+/// Remember whether this process is an Electron executable. This is called once, when the library is loaded.
+pub(crate) unsafe fn init() {
+    IN_ELECTRON.store(is_electron(c"/proc/self/exe".as_ptr()), Ordering::Relaxed);
+}
+
+/// The policy from `MODLOADER_PRELOAD_POLICY`, defaulting to [PreloadPolicy::Electron].
+pub(crate) fn policy() -> PreloadPolicy {
+    let policy = match POLICY.load(Ordering::Relaxed) {
+        UNKNOWN => {
+            let value = unsafe { libc::getenv(c"MODLOADER_PRELOAD_POLICY".as_ptr()) };
+
+            let policy = match value.is_null() {
+                true => ELECTRON,
+                false => match unsafe { CStr::from_ptr(value) }.to_bytes() {
+                    b"browser" => BROWSER_ONLY,
+                    b"all" => ALL,
+                    _ => ELECTRON,
+                },
+            };
+
+            POLICY.store(policy, Ordering::Relaxed);
+            policy
+        }
+        policy => policy,
+    };
+
+    match policy {
+        BROWSER_ONLY => PreloadPolicy::BrowserOnly,
+        ALL => PreloadPolicy::All,
+        _ => PreloadPolicy::Electron,
+    }
+}
+
+/// Whether the program at `path` is an Electron executable, i.e. there is a `resources` directory next to it.
+///
+/// Bare names that would be looked up in `PATH` never are, since Electron apps are started by path.
+unsafe fn is_electron(path: *const c_char) -> bool {
+    if path.is_null() || !CStr::from_ptr(path).to_bytes().contains(&b'/') {
+        return false;
+    }
+
+    // Room for the resolved path, and for `/resources` after its directory.
+    let mut resolved = [0 as c_char; libc::PATH_MAX as usize + 16];
+
+    // Chromium starts its child processes through /proc/self/exe, which this resolves too.
+    if libc::realpath(path, resolved.as_mut_ptr()).is_null() {
+        return false;
+    }
+
+    let is_executable = |path: *const c_char| {
+        libc::syscall(libc::SYS_faccessat, libc::AT_FDCWD, path, libc::X_OK) == 0
+    };
+
+    if !is_executable(resolved.as_ptr()) {
+        return false;
+    }
+
+    let len = CStr::from_ptr(resolved.as_ptr()).to_bytes().len();
+
+    let Some(dir_len) = resolved[..len].iter().rposition(|&c| c == b'/' as c_char) else {
+        return false;
+    };
+
+    for (i, &byte) in b"/resources\0".iter().enumerate() {
+        resolved[dir_len + i] = byte as c_char;
+    }
+
+    is_executable(resolved.as_ptr())
+}
+
+/// Whether a program started from `path` with `argv` should keep the hook library preloaded.
+///
+/// The policy only applies to what the app itself starts. Until then, e.g. when the AppImage runtime starts
+/// `AppRun` or a wrapper script starts the real executable, it is always kept.
+///
+/// Wrappers like `flatpak-spawn` and `chrome-sandbox` are let through if they start an Electron executable
+/// that is passed to them by path.
+pub(crate) unsafe fn keeps_preload(path: *const c_char, argv: *const *const c_char) -> bool {
+    if !IN_ELECTRON.load(Ordering::Relaxed) {
+        return true;
+    }
+
+    match policy() {
+        PreloadPolicy::All => true,
+        PreloadPolicy::BrowserOnly => false,
+        PreloadPolicy::Electron => {
+            if is_electron(path) {
+                return true;
+            }
+
+            if argv.is_null() {
+                return false;
+            }
+
+            (1..)
+                .map(|i| *argv.add(i))
+                .take_while(|arg| !arg.is_null())
+                .any(|arg| *arg == b'/' as c_char && is_electron(arg))
+        }
+    }
+}
+
+/// The entries of an `LD_PRELOAD` value, which the dynamic linker splits on colons and spaces.
+pub(crate) fn entries(value: &[u8]) -> impl Iterator<Item = &[u8]> {
+    value
+        .split(|&byte| byte == b':' || byte == b' ')
+        .filter(|entry| !entry.is_empty())
+}
+
+/// Join `entries` with colons into `out`, followed by a nul. Returns the length, or `None` if it doesn't fit.
+fn join_into<'a>(entries: impl Iterator<Item = &'a [u8]>, out: &mut [u8]) -> Option<usize> {
+    let mut len = 0;
+
+    for entry in entries {
+        let separator = usize::from(len > 0);
+
+        // Leave room for the nul.
+        if len + separator + entry.len() >= out.len() {
+            return None;
+        }
+
+        if separator == 1 {
+            out[len] = b':';
+        }
+
+        out[len + separator..len + separator + entry.len()].copy_from_slice(entry);
+        len += separator + entry.len();
+    }
+
+    *out.get_mut(len)? = 0;
+
+    Some(len)
+}
+
+/// Write `existing` with `library` appended to `out`, keeping the other entries and their order.
+pub(crate) fn merge_into(existing: &[u8], library: &[u8], out: &mut [u8]) -> Option<usize> {
+    merge_entries_into(entries(existing), library, out)
+}
+
+/// Like [merge_into], for entries that have already been split, e.g. to filter them.
+pub(crate) fn merge_entries_into<'a>(
+    existing: impl Iterator<Item = &'a [u8]>,
+    library: &'a [u8],
+    out: &mut [u8],
+) -> Option<usize> {
+    let others = existing.filter(|&entry| entry != library);
+
+    join_into(others.chain(std::iter::once(library)), out)
+}
+
+/// Write `existing` without `library` to `out`.
+pub(crate) fn strip_into(existing: &[u8], library: &[u8], out: &mut [u8]) -> Option<usize> {
+    join_into(entries(existing).filter(|&entry| entry != library), out)
+}
+
+/// `existing` with `library` appended, for the launcher.
+pub(crate) fn merge(existing: Option<&OsStr>, library: &OsStr) -> OsString {
+    let existing = existing.map(OsStr::as_bytes).unwrap_or_default();
+    let mut out = vec![0; existing.len() + library.len() + 2];
+
+    match merge_into(existing, library.as_bytes(), &mut out) {
+        Some(len) => {
+            out.truncate(len);
+            OsString::from_vec(out)
+        }
+        None => library.to_os_string(),
+    }
+}
+
+/// Whether an environment entry is only meant for processes we are preloaded into.
+fn is_ours(entry: &[u8]) -> bool {
+    entry.starts_with(b"ZYPAK_LD_PRELOAD=") || entry.starts_with(b"MODLOADER_")
+}
+
+/// An environment without the hook library.
+///
+/// The hooks usually run in a forked child, where the heap can't be used, so this is built in an anonymous
+/// mapping sized for the environment it is scrubbed from. It is unmapped when dropped.
+pub(crate) struct Scrubbed {
+    map: *mut c_void,
+    size: usize,
+}
+
+impl Scrubbed {
+    pub(crate) const fn new() -> Self {
+        Self {
+            map: std::ptr::null_mut(),
+            size: 0,
+        }
+    }
+
+    /// Copy `envp` without the `MODLOADER_*` variables, and with the hook library removed from `LD_PRELOAD`.
+    ///
+    /// Other `LD_PRELOAD` entries are kept. Returns the new, null-terminated environment, or `None` if it
+    /// couldn't be mapped.
+    pub(crate) unsafe fn scrub(
+        &mut self,
+        envp: *const *const c_char,
+    ) -> Option<*const *const c_char> {
+        let envp = || {
+            (0..)
+                .map(move |i| *envp.add(i))
+                .take_while(|entry| !entry.is_null())
+                .map(|entry| (entry, CStr::from_ptr(entry).to_bytes()))
+        };
+
+        let library = envp()
+            .find_map(|(_, entry)| entry.strip_prefix(b"MODLOADER_LIBRARY_PATH="))
+            .or_else(|| {
+                let library = libc::getenv(c"MODLOADER_LIBRARY_PATH".as_ptr());
+                (!library.is_null()).then(|| CStr::from_ptr(library).to_bytes())
+            });
+
+        // Room for every entry and the terminating null, then for each `LD_PRELOAD` entry and its nul.
+        let (count, preload_size) = envp().fold((0, 0), |(count, preload_size), (_, entry)| {
+            match entry.starts_with(b"LD_PRELOAD=") {
+                true => (count + 1, preload_size + entry.len() + 1),
+                false => (count + 1, preload_size),
+            }
+        });
+
+        let env_size = (count + 1) * std::mem::size_of::<*const c_char>();
+        let map = self.map_zeroed(env_size + preload_size)?;
+
+        let env = std::slice::from_raw_parts_mut(map.cast::<*const c_char>(), count + 1);
+        let preload = std::slice::from_raw_parts_mut(map.cast::<u8>().add(env_size), preload_size);
+
+        let mut len = 0;
+        let mut preload_len = 0;
+
+        for (ptr, entry) in envp() {
+            if is_ours(entry) {
+                continue;
+            }
+
+            let ptr = match (entry.strip_prefix(b"LD_PRELOAD="), library) {
+                (Some(existing), Some(library)) => {
+                    let out = &mut preload[preload_len..preload_len + entry.len() + 1];
+                    let prefix = b"LD_PRELOAD=";
+                    out[..prefix.len()].copy_from_slice(prefix);
+
+                    // Removing entries never makes the value longer, so this always fits.
+                    match strip_into(existing, library, &mut out[prefix.len()..])? {
+                        0 => continue,
+                        stripped => {
+                            preload_len += prefix.len() + stripped + 1;
+                            out.as_ptr().cast()
+                        }
+                    }
+                }
+                _ => ptr,
+            };
+
+            env[len] = ptr;
+            len += 1;
+        }
+
+        env[len] = std::ptr::null();
+
+        Some(env.as_ptr())
+    }
+
+    /// Map `size` zeroed bytes, replacing any earlier mapping.
+    unsafe fn map_zeroed(&mut self, size: usize) -> Option<*mut c_void> {
+        self.unmap();
+
+        let map = libc::mmap(
+            std::ptr::null_mut(),
+            size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+
+        if map == libc::MAP_FAILED {
+            return None;
+        }
+
+        self.map = map;
+        self.size = size;
+
+        Some(map)
+    }
+
+    fn unmap(&mut self) {
+        if !self.map.is_null() {
+            unsafe { libc::munmap(self.map, self.size) };
+            self.map = std::ptr::null_mut();
+        }
+    }
+}
+
+impl Drop for Scrubbed {
+    fn drop(&mut self) {
+        self.unmap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ffi::CString;
+
+    const LIBRARY: &[u8] = b"/opt/hook/libelectron_hook.so";
+
+    /// Scrub `env` and read back the result.
+    fn scrubbed(env: &[&str]) -> Vec<String> {
+        let env: Vec<CString> = env
+            .iter()
+            .map(|entry| CString::new(*entry).unwrap())
+            .collect();
+        let envp: Vec<*const c_char> = env
+            .iter()
+            .map(|entry| entry.as_ptr())
+            .chain(std::iter::once(std::ptr::null()))
+            .collect();
+
+        let mut scrubbed = Scrubbed::new();
+
+        unsafe {
+            let envp = scrubbed.scrub(envp.as_ptr()).unwrap();
+
+            (0..)
+                .map(|i| *envp.add(i))
+                .take_while(|entry| !entry.is_null())
+                .map(|entry| CStr::from_ptr(entry).to_str().unwrap().to_string())
+                .collect()
+        }
+    }
+
+    fn stripped(existing: &[u8]) -> Option<String> {
+        let mut out = [0; 256];
+        let len = strip_into(existing, LIBRARY, &mut out)?;
+
+        assert_eq!(out[len], 0);
+        Some(String::from_utf8(out[..len].to_vec()).unwrap())
+    }
+
+    #[test]
+    fn strip_splits_like_the_dynamic_linker() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"/opt/hook/libelectron_hook.so", ""),
+            (b"/a.so /opt/hook/libelectron_hook.so", "/a.so"),
+            (b"/a.so:/opt/hook/libelectron_hook.so /b.so", "/a.so:/b.so"),
+            (b"::/a.so  :/opt/hook/libelectron_hook.so:", "/a.so"),
+            (
+                b"/opt/hook/libelectron_hook.so:/a.so:/opt/hook/libelectron_hook.so",
+                "/a.so",
+            ),
+            (
+                b"/opt/hook/libelectron_hook.so.1",
+                "/opt/hook/libelectron_hook.so.1",
+            ),
+            (b"", ""),
+        ];
+
+        for &(existing, expected) in cases {
+            assert_eq!(stripped(existing).as_deref(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn join_fills_the_buffer_exactly() {
+        // `a:bc` and its nul.
+        let mut out = [0xff; 5];
+        assert_eq!(join_into([&b"a"[..], b"bc"].into_iter(), &mut out), Some(4));
+        assert_eq!(&out, b"a:bc\0");
+
+        let mut out = [0xff; 4];
+        assert_eq!(join_into([&b"a"[..], b"bc"].into_iter(), &mut out), None);
+
+        let mut out = [0xff; 1];
+        assert_eq!(join_into(std::iter::empty(), &mut out), Some(0));
+        assert_eq!(join_into(std::iter::empty(), &mut []), None);
+    }
+
+    #[test]
+    fn merge_appends_the_library_once() {
+        let library = OsStr::from_bytes(LIBRARY);
+        let merged = |existing: Option<&str>| merge(existing.map(OsStr::new), library);
+
+        assert_eq!(merged(None), library);
+        assert_eq!(merged(Some("")), library);
+        assert_eq!(merged(Some(" : ")), library);
+        assert_eq!(
+            merged(Some("/a.so /b.so")),
+            "/a.so:/b.so:/opt/hook/libelectron_hook.so"
+        );
+
+        // An existing entry is moved to the end, after the others, which keep their order.
+        assert_eq!(merged(Some("/opt/hook/libelectron_hook.so")), library);
+        assert_eq!(
+            merged(Some("/b.so:/opt/hook/libelectron_hook.so:/a.so")),
+            "/b.so:/a.so:/opt/hook/libelectron_hook.so"
+        );
+    }
+
+    #[test]
+    fn scrub_removes_our_variables() {
+        let env = scrubbed(&[
+            "PATH=/usr/bin",
+            "MODLOADER_LIBRARY_PATH=/opt/hook/libelectron_hook.so",
+            "MODLOADER_ASAR_PATH=/tmp/app.asar",
+            "ZYPAK_LD_PRELOAD=/opt/hook/libelectron_hook.so",
+            "LD_PRELOAD=/a.so /opt/hook/libelectron_hook.so",
+            "HOME=/home/user",
+        ]);
+
+        assert_eq!(
+            env,
+            ["PATH=/usr/bin", "LD_PRELOAD=/a.so", "HOME=/home/user"]
+        );
+    }
+
+    #[test]
+    fn scrub_drops_ld_preload_with_only_our_library() {
+        let env = scrubbed(&[
+            "MODLOADER_LIBRARY_PATH=/opt/hook/libelectron_hook.so",
+            "LD_PRELOAD=/opt/hook/libelectron_hook.so",
+            "LD_PRELOAD=: /opt/hook/libelectron_hook.so:",
+            "HOME=/home/user",
+        ]);
+
+        assert_eq!(env, ["HOME=/home/user"]);
+    }
+
+    #[test]
+    fn scrub_keeps_each_ld_preload_entry() {
+        let env = scrubbed(&[
+            "MODLOADER_LIBRARY_PATH=/opt/hook/libelectron_hook.so",
+            "LD_PRELOAD=/a.so:/opt/hook/libelectron_hook.so",
+            "LD_PRELOAD=/opt/hook/libelectron_hook.so /b.so",
+        ]);
+
+        assert_eq!(env, ["LD_PRELOAD=/a.so", "LD_PRELOAD=/b.so"]);
+    }
+
+    #[test]
+    fn scrub_handles_large_environments() {
+        let mut env: Vec<String> = (0..10_000).map(|i| format!("VAR_{i}=value")).collect();
+        env.push("MODLOADER_LIBRARY_PATH=/opt/hook/libelectron_hook.so".to_string());
+        env.push(format!(
+            "LD_PRELOAD={}:/opt/hook/libelectron_hook.so",
+            "/a.so:".repeat(2000)
+        ));
+
+        let env: Vec<&str> = env.iter().map(String::as_str).collect();
+        let scrubbed = scrubbed(&env);
+
+        assert_eq!(scrubbed.len(), 10_001);
+        assert_eq!(scrubbed[..10_000], env[..10_000]);
+        assert_eq!(
+            scrubbed[10_000],
+            format!("LD_PRELOAD={}", ["/a.so"; 2000].join(":"))
+        );
+    }
+}
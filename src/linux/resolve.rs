@@ -0,0 +1,689 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::discovery::{is_elf, which};
+
+/// How many wrappers deep to follow, in case scripts exec each other in a loop.
+const MAX_DEPTH: usize = 8;
+
+/// The real Electron binary behind an executable, and what its wrappers would have passed to it.
+#[derive(Debug, Clone)]
+pub(crate) struct Resolved {
+    /// The Electron binary, with symlinks resolved.
+    pub(crate) executable: PathBuf,
+    /// The `resources` directory of the app, if it could be found.
+    pub(crate) resources_dir: Option<PathBuf>,
+    /// Flags the wrappers would have passed before the user's arguments.
+    pub(crate) args: Vec<String>,
+    /// Variables the wrappers would have exported, in the order they were set.
+    pub(crate) env: Vec<(String, String)>,
+}
+
+impl Resolved {
+    /// Launch `executable` as it is, e.g. when it can't be read.
+    pub(crate) fn as_is(executable: &Path) -> Self {
+        Self {
+            executable: executable.to_path_buf(),
+            resources_dir: None,
+            args: Vec::new(),
+            env: Vec::new(),
+        }
+    }
+}
+
+/// Follow symlinks and wrapper scripts from `executable` to the real Electron binary.
+///
+/// This understands scripts that end in an `exec` line, like Arch's electron wrappers and Nix's
+/// `makeWrapper`. If a wrapper can't be understood, it is launched as-is.
+pub(crate) fn resolve(executable: &Path) -> Resolved {
+    let mut resolved = Resolved::as_is(executable);
+
+    let mut wrapper_args = Vec::new();
+
+    for _ in 0..MAX_DEPTH {
+        let Ok(path) = resolved.executable.canonicalize() else {
+            break;
+        };
+
+        resolved.executable = path;
+
+        if is_elf(&resolved.executable) {
+            break;
+        }
+
+        let Some(wrapper) = parse_wrapper(&resolved.executable, &resolved.env) else {
+            break;
+        };
+
+        resolved.executable = wrapper.program;
+        resolved.env.extend(wrapper.env);
+
+        // Each wrapper's flags come before the ones added by the wrapper that ran it.
+        wrapper_args.push(wrapper.args);
+    }
+
+    resolved.args = wrapper_args.into_iter().rev().flatten().collect();
+    resolved.resources_dir = find_resources_dir(&resolved.executable, &resolved.args);
+
+    resolved
+}
+
+/// Find the `resources` directory next to the binary, or the directory of an ASAR passed to a system Electron.
+fn find_resources_dir(executable: &Path, args: &[String]) -> Option<PathBuf> {
+    let resources_dir = executable.parent()?.join("resources");
+
+    if resources_dir.join("app.asar").is_file() {
+        return Some(resources_dir);
+    }
+
+    let app_asar = args
+        .iter()
+        .map(Path::new)
+        .find(|arg| arg.extension().is_some_and(|ext| ext == "asar") && arg.is_file());
+
+    match app_asar {
+        Some(app_asar) => app_asar.parent().map(Path::to_path_buf),
+        None => resources_dir.is_dir().then_some(resources_dir),
+    }
+}
+
+struct Wrapper {
+    program: PathBuf,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+}
+
+/// Read the first `exec` in a shell script that always runs and resolves to a program.
+///
+/// `exec`s inside `if`, `case`, loops, groups and functions, or after `&&` and `||`, may not run, e.g. when
+/// they are a fallback, so they are skipped.
+fn parse_wrapper(script: &Path, env: &[(String, String)]) -> Option<Wrapper> {
+    let contents = std::fs::read_to_string(script).ok()?;
+
+    if !contents.starts_with("#!") {
+        return None;
+    }
+
+    let mut vars = env.iter().cloned().collect::<HashMap<String, String>>();
+    vars.insert("0".into(), script.to_string_lossy().into_owned());
+
+    let mut exports = Vec::new();
+
+    // How many compound commands deep we are.
+    let mut depth = 0usize;
+
+    let lines = contents.replace("\\\n", "");
+    let commands = lines.lines().flat_map(commands);
+
+    for (command, conditional) in commands {
+        let mut words = split_words(command, &vars);
+
+        match words.first().and_then(Word::text) {
+            Some("if" | "case" | "while" | "until" | "for" | "{") => depth += 1,
+            Some("fi" | "esac" | "done" | "}") => depth = depth.saturating_sub(1),
+            // `name() {` and `function name {`
+            _ if words.iter().any(|word| word.text() == Some("{")) => depth += 1,
+            _ => {}
+        }
+
+        let exported = words
+            .first()
+            .is_some_and(|word| word.text() == Some("export"));
+        if exported {
+            words.remove(0);
+        }
+
+        // `NAME=value` or `export NAME=value`
+        if let [Word::Text(word)] = words.as_slice() {
+            if let Some((name, value)) = assignment(word) {
+                vars.insert(name.into(), value.into());
+
+                if exported {
+                    exports.push((name.to_string(), value.to_string()));
+                }
+
+                continue;
+            }
+        }
+
+        if depth > 0 || conditional || words.first().and_then(Word::text) != Some("exec") {
+            continue;
+        }
+
+        let mut words = words.into_iter().skip(1).peekable();
+
+        // `exec -a name`, `exec -c` and `exec -l`
+        while let Some(Word::Text(flag)) = words.peek() {
+            match flag.as_str() {
+                "-a" => {
+                    words.next();
+                }
+                "-c" | "-l" | "-cl" | "-lc" => {}
+                _ => break,
+            }
+            words.next();
+        }
+
+        let Some(Word::Text(program)) = words.next() else {
+            continue;
+        };
+
+        let Some(program) = which(&program) else {
+            continue;
+        };
+
+        let args = words
+            .filter_map(|word| match word {
+                Word::Text(arg) => Some(arg),
+                Word::AllArgs => None,
+            })
+            .collect();
+
+        return Some(Wrapper {
+            program,
+            args,
+            env: exports,
+        });
+    }
+
+    None
+}
+
+/// Split a line of shell into its commands at `;`, `&`, `|`, `&&` and `||`, with whether each one only runs
+/// depending on the one before it, i.e. after `&&`, `||` or `|`.
+fn commands(line: &str) -> Vec<(&str, bool)> {
+    let bytes = line.as_bytes();
+    let mut commands = Vec::new();
+
+    let mut start = 0;
+    let mut conditional = false;
+    let mut quote = None;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+
+        match (quote, c) {
+            (Some(b'\''), b'\'') => quote = None,
+            (Some(b'\''), _) => {}
+            (_, b'\\') => i += 1,
+            (Some(_), b'"') => quote = None,
+            (Some(_), _) => {}
+            (None, b'\'' | b'"') => quote = Some(c),
+            (None, b'#') if i == 0 || bytes[i - 1].is_ascii_whitespace() => break,
+            // Redirections like `>&2`
+            (None, b'&') if i > 0 && matches!(bytes[i - 1], b'>' | b'<') => {}
+            (None, b';' | b'&' | b'|') => {
+                commands.push((&line[start..i], conditional));
+
+                let doubled = bytes.get(i + 1) == Some(&c);
+                conditional = c == b'|' || (c == b'&' && doubled);
+
+                i += usize::from(doubled);
+                start = i + 1;
+            }
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    commands.push((&line[start.min(line.len())..i.min(line.len())], conditional));
+    commands
+}
+
+/// Split `word` into `(name, value)` if it's a variable assignment.
+fn assignment(word: &str) -> Option<(&str, &str)> {
+    let (name, value) = word.split_once('=')?;
+
+    let valid_name = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    valid_name.then_some((name, value))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Word {
+    Text(String),
+    /// `"$@"`, where the wrapper passes on its own arguments.
+    AllArgs,
+}
+
+impl Word {
+    fn text(&self) -> Option<&str> {
+        match self {
+            Word::Text(text) => Some(text),
+            Word::AllArgs => None,
+        }
+    }
+}
+
+/// Split a line of shell into words, expanding variables from `vars` and then the environment.
+///
+/// This only handles quoting and simple parameter expansion, which is all wrappers normally use.
+fn split_words(line: &str, vars: &HashMap<String, String>) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        match chars.peek() {
+            None | Some('#') | Some(';') | Some('&') | Some('|') => break,
+            _ => {}
+        }
+
+        let mut word = String::new();
+        let mut quoted = false;
+        let mut expanded = false;
+        let mut all_args = false;
+        let mut in_double_quotes = false;
+
+        while let Some(&c) = chars.peek() {
+            if !in_double_quotes && (c.is_whitespace() || matches!(c, ';' | '&' | '|')) {
+                break;
+            }
+
+            chars.next();
+
+            match c {
+                '\'' if !in_double_quotes => {
+                    quoted = true;
+                    word.extend(chars.by_ref().take_while(|&c| c != '\''));
+                }
+                '"' => {
+                    quoted = true;
+                    in_double_quotes = !in_double_quotes;
+                }
+                '\\' => word.extend(chars.next()),
+                '$' => {
+                    expanded = true;
+
+                    match expand(&mut chars, vars) {
+                        Some(value) => word.push_str(&value),
+                        None => all_args = true,
+                    }
+                }
+                c => word.push(c),
+            }
+        }
+
+        if all_args {
+            words.push(Word::AllArgs);
+        } else if !word.is_empty() || (quoted && !expanded) {
+            words.push(Word::Text(word));
+        }
+    }
+
+    words
+}
+
+/// Expand the parameter after a `$`, returning `None` for `$@` and `$*`.
+fn expand(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    vars: &HashMap<String, String>,
+) -> Option<String> {
+    let lookup = |name: &str| vars.get(name).cloned().or_else(|| std::env::var(name).ok());
+
+    if chars.next_if(|&c| c == '@' || c == '*').is_some() {
+        return None;
+    }
+
+    if chars.next_if_eq(&'{').is_none() {
+        let mut name = String::new();
+
+        if let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+            name.push(digit);
+        } else {
+            while let Some(c) = chars.next_if(|&c| c.is_ascii_alphanumeric() || c == '_') {
+                name.push(c);
+            }
+        }
+
+        if name.is_empty() {
+            return Some("$".into());
+        }
+
+        return Some(lookup(&name).unwrap_or_default());
+    }
+
+    let mut inner = String::new();
+    let mut depth = 1;
+
+    for c in chars.by_ref() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            _ => {}
+        }
+        inner.push(c);
+    }
+
+    if inner == "@" || inner == "*" {
+        return None;
+    }
+
+    let name_len = inner
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(inner.len());
+    let (name, operator) = inner.split_at(name_len);
+
+    let value = lookup(name);
+    let is_set_and_not_null = value.as_ref().is_some_and(|value| !value.is_empty());
+
+    // The operand of ${NAME-word} and friends can be quoted and contain expansions itself.
+    let operand = |word: &str| {
+        split_words(word, vars)
+            .into_iter()
+            .filter_map(|word| word.text().map(str::to_string))
+            .collect::<Vec<String>>()
+            .join(" ")
+    };
+
+    let expanded = if let Some(word) = operator.strip_prefix(":-") {
+        if is_set_and_not_null {
+            value
+        } else {
+            Some(operand(word))
+        }
+    } else if let Some(word) = operator.strip_prefix(":+") {
+        is_set_and_not_null.then(|| operand(word))
+    } else if let Some(word) = operator.strip_prefix('-') {
+        value.or_else(|| Some(operand(word)))
+    } else if let Some(word) = operator.strip_prefix('+') {
+        value.map(|_| operand(word))
+    } else if operator.is_empty() {
+        value
+    } else {
+        // Arrays, substrings and the like aren't supported, and expand to nothing.
+        None
+    };
+
+    Some(expanded.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "electron-hook-resolve-{}-{name}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    fn write(path: &Path, contents: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    /// Something [is_elf] takes for a binary.
+    fn binary(path: &Path) {
+        write(path, "\x7fELF");
+    }
+
+    fn text(words: Vec<Word>) -> Vec<Option<String>> {
+        words
+            .into_iter()
+            .map(|word| word.text().map(str::to_string))
+            .collect()
+    }
+
+    #[test]
+    fn split_words_quotes_and_expands() {
+        let vars = HashMap::from([
+            ("EH_NAME".to_string(), "electron31".to_string()),
+            ("EH_EMPTY".to_string(), String::new()),
+        ]);
+
+        type Case<'a> = (&'a str, &'a [Option<&'a str>]);
+
+        let cases: &[Case] = &[
+            (
+                "exec /usr/lib/$EH_NAME/electron",
+                &[Some("exec"), Some("/usr/lib/electron31/electron")],
+            ),
+            (
+                "a 'b c' \"d $EH_NAME\" e\\ f",
+                &[Some("a"), Some("b c"), Some("d electron31"), Some("e f")],
+            ),
+            ("${EH_NAME}-flags.conf", &[Some("electron31-flags.conf")]),
+            ("${EH_UNSET:-$HOME_EH/.config}", &[Some("/.config")]),
+            (
+                "${EH_EMPTY:-default} ${EH_EMPTY-default}",
+                &[Some("default")],
+            ),
+            (
+                "${EH_NAME:+--name=$EH_NAME} ${EH_UNSET:+x}",
+                &[Some("--name=electron31")],
+            ),
+            ("${EH_EMPTY+set} ${EH_UNSET+set}", &[Some("set")]),
+            ("${EH_NAME:-'quoted default'}", &[Some("electron31")]),
+            ("\"$@\" $* \"${@}\"", &[None, None, None]),
+            ("'' \"\" $EH_UNSET \"$EH_UNSET\"", &[Some(""), Some("")]),
+            ("\"${fd[@]}\" x # comment", &[Some("x")]),
+            ("a; b", &[Some("a")]),
+        ];
+
+        for (line, expected) in cases {
+            let expected = expected
+                .iter()
+                .map(|word| word.map(str::to_string))
+                .collect::<Vec<_>>();
+
+            assert_eq!(text(split_words(line, &vars)), expected, "{line}");
+        }
+    }
+
+    #[test]
+    fn commands_split_at_separators() {
+        assert_eq!(
+            commands("if [ -x a ]; then exec a \"$@\"; fi"),
+            [
+                ("if [ -x a ]", false),
+                (" then exec a \"$@\"", false),
+                (" fi", false)
+            ]
+        );
+        assert_eq!(
+            commands("[ -n \"$X\" ] && exec b || exec c | d"),
+            [
+                ("[ -n \"$X\" ] ", false),
+                (" exec b ", true),
+                (" exec c ", true),
+                (" d", true)
+            ]
+        );
+        assert_eq!(
+            commands("echo 'a; b' \"c && d\" >&2 # e; f"),
+            [("echo 'a; b' \"c && d\" >&2 ", false)]
+        );
+        assert_eq!(
+            commands("wayland) exec a ;;"),
+            [("wayland) exec a ", false), ("", false)]
+        );
+    }
+
+    #[test]
+    fn resolve_arch_wrappers() {
+        let dir = temp_dir("arch");
+        let electron = dir.join("usr/lib/electron31/electron");
+        let app_asar = dir.join("usr/lib/element/app.asar");
+
+        binary(&electron);
+        write(&app_asar, "");
+
+        // /usr/bin/electron31 from the electron31 package.
+        write(
+            &dir.join("usr/bin/electron31"),
+            &format!(
+                r#"#!/usr/bin/bash
+
+set -euo pipefail
+
+name=electron31
+flags_file="${{XDG_CONFIG_HOME:-$HOME/.config}}/${{name}}-flags.conf"
+
+fd=()
+if [[ -f "${{flags_file}}" ]]; then
+    mapfile -t < "${{flags_file}}"
+fi
+
+for line in "${{MAPFILE[@]}}"; do
+    if [[ ! "${{line}}" =~ ^[[:space:]]*#.* ]] && [[ -n "${{line}}" ]]; then
+        fd+=("${{line}}")
+    fi
+done
+
+export CHROME_VERSION_EXTRA="Arch Linux"
+
+exec {}/usr/lib/${{name}}/electron "${{fd[@]}}" "$@"
+"#,
+                dir.display()
+            ),
+        );
+
+        // /usr/bin/element-desktop
+        write(
+            &dir.join("usr/bin/element-desktop"),
+            &format!(
+                "#!/bin/sh\nexec {}/usr/bin/electron31 {} \"$@\"\n",
+                dir.display(),
+                app_asar.display()
+            ),
+        );
+
+        let resolved = resolve(&dir.join("usr/bin/element-desktop"));
+
+        assert_eq!(resolved.executable, electron);
+        assert_eq!(resolved.args, [app_asar.to_string_lossy()]);
+        assert_eq!(
+            resolved.env,
+            [("CHROME_VERSION_EXTRA".to_string(), "Arch Linux".to_string())]
+        );
+        assert_eq!(
+            resolved.resources_dir,
+            app_asar.parent().map(Path::to_path_buf)
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolve_nix_make_wrapper() {
+        let dir = temp_dir("nix");
+        let store = dir.join("nix/store/8kq5cfbmgsjd4jfakq4bhzb2pxxcvjqn-discord-0.0.50");
+        let wrapped = store.join("opt/Discord/.Discord-wrapped");
+
+        binary(&wrapped);
+        write(&store.join("opt/Discord/resources/app.asar"), "");
+
+        // Written by `makeWrapper ... --prefix PATH : ... --set-default NIXOS_OZONE_WL 1 --add-flags ...`.
+        write(
+            &store.join("opt/Discord/Discord"),
+            &format!(
+                r#"#! /nix/store/4bj2kxdm1462fzcc2i2s4dn33g2angcc-bash-5.2p32/bin/bash -e
+PATH=${{PATH:+':'$PATH':'}}
+PATH=${{PATH/':''/nix/store/zr1fw6hb5yhgajc3k5bk3kfhz2pmjpw3-xdg-utils-1.2.1/bin'':'/':'}}
+PATH='/nix/store/zr1fw6hb5yhgajc3k5bk3kfhz2pmjpw3-xdg-utils-1.2.1/bin'$PATH
+PATH=${{PATH#':'}}
+PATH=${{PATH%':'}}
+export PATH
+export EH_NIX_OZONE_WL=${{EH_NIX_OZONE_WL-'1'}}
+exec -a "$0" "{}"  --enable-features=UseOzonePlatform "$@"
+"#,
+                wrapped.display()
+            ),
+        );
+
+        let resolved = resolve(&store.join("opt/Discord/Discord"));
+
+        assert_eq!(resolved.executable, wrapped);
+        assert_eq!(resolved.args, ["--enable-features=UseOzonePlatform"]);
+        assert_eq!(
+            resolved.env,
+            [("EH_NIX_OZONE_WL".to_string(), "1".to_string())]
+        );
+        assert_eq!(
+            resolved.resources_dir,
+            Some(store.join("opt/Discord/resources"))
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolve_skips_conditional_execs() {
+        let dir = temp_dir("conditional");
+
+        for name in ["ptb", "debug", "wayland", "function", "stable"] {
+            binary(&dir.join(name).join("Discord"));
+        }
+
+        let target = |name: &str| dir.join(name).join("Discord").display().to_string();
+
+        write(
+            &dir.join("discord"),
+            &format!(
+                r#"#!/bin/sh
+if [ -x {ptb} ]; then
+    exec {ptb} "$@"
+fi
+[ -n "$DISCORD_DEBUG" ] && exec {debug} --debug "$@"
+case "$XDG_SESSION_TYPE" in
+    wayland) exec {wayland} --ozone-platform=wayland "$@" ;;
+esac
+start() {{ exec {function} "$@"; }}
+run()
+{{
+    exec {function} "$@"
+}}
+exec {stable} --stable "$@"
+"#,
+                ptb = target("ptb"),
+                debug = target("debug"),
+                wayland = target("wayland"),
+                function = target("function"),
+                stable = target("stable"),
+            ),
+        );
+
+        let resolved = resolve(&dir.join("discord"));
+
+        assert_eq!(resolved.executable, dir.join("stable/Discord"));
+        assert_eq!(resolved.args, ["--stable"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolve_gives_up_on_exec_loops() {
+        let dir = temp_dir("loop");
+        let (a, b) = (dir.join("a"), dir.join("b"));
+
+        write(
+            &a,
+            &format!("#!/bin/sh\nexec {} --from-a \"$@\"\n", b.display()),
+        );
+        write(
+            &b,
+            &format!("#!/bin/sh\nexec {} --from-b \"$@\"\n", a.display()),
+        );
+
+        let resolved = resolve(&a);
+
+        assert!(resolved.executable == a || resolved.executable == b);
+        assert_eq!(resolved.args.len(), MAX_DEPTH);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,586 @@
+//! Translation of host paths into Flatpak sandbox paths
+//!
+//! Every file electron-hook hands to a Flatpak app (the library, the ASAR, the mod entrypoint...) has to be
+//! reachable from inside the sandbox. [SandboxPaths] works out where each host path appears in the sandbox,
+//! and the minimal set of `--filesystem=` grants needed to make them visible.
+//!
+//! This module is only available on Linux.
+//!
+//! ```rust
+//! use std::path::Path;
+//! use electron_hook::sandbox::{Access, SandboxPaths};
+//!
+//! let mut sandbox = SandboxPaths::new();
+//!
+//! let library = sandbox.expose(Path::new("/usr/lib/libmoonlight.so"), Access::ReadOnly);
+//! let asar = sandbox.expose(Path::new("/var/home/me/.cache/electron-hook/asar"), Access::ReadOnly);
+//! let mods = sandbox.expose(Path::new("/mnt/games/mods/moonlight"), Access::Create);
+//!
+//! assert_eq!(library.unwrap(), Path::new("/run/host/usr/lib/libmoonlight.so"));
+//! assert_eq!(asar.unwrap(), Path::new("/var/home/me/.cache/electron-hook/asar"));
+//! assert_eq!(mods.unwrap(), Path::new("/mnt/games/mods/moonlight"));
+//!
+//! // A path inside an already granted directory doesn't need a grant of its own.
+//! sandbox.expose(Path::new("/var/home/me/.cache/electron-hook/asar/moonlight.asar"), Access::ReadOnly);
+//!
+//! assert_eq!(
+//!     sandbox.filesystem_args(),
+//!     [
+//!         "--filesystem=host-os:ro",
+//!         "--filesystem=/mnt/games/mods/moonlight:create",
+//!         "--filesystem=/var/home/me/.cache/electron-hook/asar:ro",
+//!     ]
+//! );
+//! ```
+//!
+//! It also handles the opposite direction with [FlatpakInfo], for when electron-hook itself runs inside a
+//! Flatpak and needs to hand its own files to the host.
+
+use std::path::{Component, Path, PathBuf};
+
+/// Host directories that make up the host OS. With `--filesystem=host-os`, they appear under `/run/host`.
+const HOST_OS_DIRS: &[&str] = &["/usr", "/lib", "/lib32", "/lib64", "/bin", "/sbin"];
+
+/// With `--filesystem=host-etc`, the host's `/etc` appears under `/run/host`.
+const HOST_ETC_DIR: &str = "/etc";
+
+/// Host directories that Flatpak replaces with its own, so they can't be shared with the sandbox.
+const RESERVED_DIRS: &[&str] = &[
+    "/app",
+    "/dev",
+    "/proc",
+    "/sys",
+    "/run",
+    "/var",
+    "/.flatpak-info",
+];
+
+/// Directories below [RESERVED_DIRS] that can still be shared.
+///
+/// `/var/home`, `/var/mnt` and `/var/roothome` are where `/home`, `/mnt` and `/root` really live on
+/// ostree-based systems such as Fedora Silverblue.
+const SHAREABLE_RESERVED_DIRS: &[&str] = &[
+    "/run/media",
+    "/var/home",
+    "/var/mnt",
+    "/var/roothome",
+    "/var/tmp",
+];
+
+/// How much access the sandbox is given to a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    /// The sandbox can only read the path. Maps to `:ro`.
+    ReadOnly,
+    /// The sandbox can read and write the path, and create it if it doesn't exist. Maps to `:create`.
+    Create,
+}
+
+/// Where a host path can be found from inside the sandbox.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    /// Mounted at the same path, once it has been granted with `--filesystem=<path>`.
+    Shared(PathBuf),
+    /// Part of the host OS, mounted under `/run/host` with `--filesystem=host-os:ro`.
+    HostOs(PathBuf),
+    /// Part of the host's `/etc`, mounted under `/run/host` with `--filesystem=host-etc:ro`.
+    HostEtc(PathBuf),
+    /// Not reachable from the sandbox. The file has to be copied somewhere that is.
+    Private,
+}
+
+/// Lexically normalize a path, removing `.`, `..` and duplicate slashes without touching the filesystem.
+///
+/// ```rust
+/// use std::path::Path;
+/// use electron_hook::sandbox::normalize;
+///
+/// assert_eq!(normalize(Path::new("/usr//lib/./../lib/libfoo.so")), Path::new("/usr/lib/libfoo.so"));
+/// assert_eq!(normalize(Path::new("/../etc")), Path::new("/etc"));
+/// ```
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Whether `path` is `dir` or inside it.
+fn is_within(path: &Path, dir: &str) -> bool {
+    path.starts_with(dir)
+}
+
+/// Work out where an absolute host path can be found from inside the sandbox.
+///
+/// This is purely lexical, so symlinks should be resolved beforehand if needed. Relative paths are never
+/// reachable, as the sandbox's working directory is unrelated to ours.
+///
+/// ```rust
+/// use std::path::{Path, PathBuf};
+/// use electron_hook::sandbox::{locate, Location};
+///
+/// assert_eq!(locate(Path::new("/lib64/libfoo.so")), Location::HostOs("/run/host/lib64/libfoo.so".into()));
+/// assert_eq!(locate(Path::new("/etc/foo.conf")), Location::HostEtc("/run/host/etc/foo.conf".into()));
+/// assert_eq!(locate(Path::new("/tmp/foo.asar")), Location::Shared("/tmp/foo.asar".into()));
+/// assert_eq!(locate(Path::new("/run/media/me/usb")), Location::Shared("/run/media/me/usb".into()));
+/// assert_eq!(locate(Path::new("/run/user/1000/foo")), Location::Private);
+/// assert_eq!(locate(Path::new("/var/lib/foo")), Location::Private);
+/// assert_eq!(locate(Path::new("libfoo.so")), Location::Private);
+/// ```
+pub fn locate(host_path: &Path) -> Location {
+    if !host_path.is_absolute() {
+        return Location::Private;
+    }
+
+    let path = normalize(host_path);
+
+    if HOST_OS_DIRS.iter().any(|dir| is_within(&path, dir)) {
+        return Location::HostOs(
+            Path::new("/run/host").join(path.strip_prefix("/").unwrap_or(&path)),
+        );
+    }
+
+    if is_within(&path, HOST_ETC_DIR) {
+        return Location::HostEtc(
+            Path::new("/run/host").join(path.strip_prefix("/").unwrap_or(&path)),
+        );
+    }
+
+    let shareable = SHAREABLE_RESERVED_DIRS
+        .iter()
+        .any(|dir| is_within(&path, dir));
+    let reserved = RESERVED_DIRS.iter().any(|dir| is_within(&path, dir));
+
+    if reserved && !shareable || path == Path::new("/") {
+        return Location::Private;
+    }
+
+    Location::Shared(path)
+}
+
+/// Collects the host paths exposed to a Flatpak sandbox, and the grants needed for them.
+#[derive(Debug, Clone, Default)]
+pub struct SandboxPaths {
+    host_os: bool,
+    host_etc: bool,
+    grants: Vec<(PathBuf, Access)>,
+}
+
+impl SandboxPaths {
+    /// Create an empty set of sandbox paths.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make `host_path` visible in the sandbox, returning where it can be found.
+    ///
+    /// Returns `None` if the path can't be shared, see [Location::Private].
+    pub fn expose(&mut self, host_path: &Path, access: Access) -> Option<PathBuf> {
+        match locate(host_path) {
+            Location::Shared(path) => {
+                self.grants.push((path.clone(), access));
+                Some(path)
+            }
+            Location::HostOs(path) => {
+                self.host_os = true;
+                Some(path)
+            }
+            Location::HostEtc(path) => {
+                self.host_etc = true;
+                Some(path)
+            }
+            Location::Private => None,
+        }
+    }
+
+    /// The `--filesystem=` arguments for `flatpak run`.
+    ///
+    /// Paths that are already covered by a grant for one of their parent directories, with at least the same
+    /// access, are left out.
+    pub fn filesystem_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+
+        if self.host_os {
+            args.push("--filesystem=host-os:ro".to_string());
+        }
+
+        if self.host_etc {
+            args.push("--filesystem=host-etc:ro".to_string());
+        }
+
+        let mut grants = self.grants.clone();
+        grants.sort();
+
+        let needed = grants.iter().enumerate().filter(|(index, (path, access))| {
+            !grants
+                .iter()
+                .enumerate()
+                .any(|(other_index, (other_path, other_access))| {
+                    let covers = path.starts_with(other_path) && other_access >= access;
+                    let duplicate = other_path == path && other_access == access;
+
+                    // Only keep the first of any exact duplicates.
+                    covers && (!duplicate || other_index < *index)
+                })
+        });
+
+        args.extend(needed.map(|(_, (path, access))| {
+            let suffix = match access {
+                Access::ReadOnly => "ro",
+                Access::Create => "create",
+            };
+            format!("--filesystem={}:{suffix}", path.display())
+        }));
+
+        args
+    }
+}
+
+/// Details about the Flatpak sandbox we are running in, from `/.flatpak-info`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlatpakInfo {
+    /// Our own app ID.
+    pub app_id: String,
+    /// Where our `/app` is on the host.
+    pub app_path: Option<PathBuf>,
+    /// Where our `/usr` is on the host.
+    pub runtime_path: Option<PathBuf>,
+}
+
+impl FlatpakInfo {
+    /// Read `/.flatpak-info`, returning `None` if we aren't running inside a Flatpak.
+    pub fn current() -> Option<&'static FlatpakInfo> {
+        use std::sync::OnceLock;
+
+        static INFO: OnceLock<Option<FlatpakInfo>> = OnceLock::new();
+
+        INFO.get_or_init(|| {
+            std::fs::read_to_string("/.flatpak-info")
+                .ok()
+                .map(|contents| FlatpakInfo::parse(&contents))
+        })
+        .as_ref()
+    }
+
+    /// Parse the contents of a `.flatpak-info` file.
+    pub fn parse(contents: &str) -> Self {
+        let mut info = Self::default();
+        let mut section = "";
+
+        for line in contents.lines().map(str::trim) {
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                section = name;
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+
+            match (section, key.trim()) {
+                ("Application", "name") => info.app_id = value.trim().to_string(),
+                ("Instance", "app-path") => info.app_path = Some(value.trim().into()),
+                ("Instance", "runtime-path") => info.runtime_path = Some(value.trim().into()),
+                _ => {}
+            }
+        }
+
+        info
+    }
+
+    /// Translate a path inside our sandbox into the path of the same file on the host.
+    ///
+    /// Returns `None` for paths that only exist inside the sandbox, such as `/tmp`.
+    ///
+    /// ```rust
+    /// use std::path::Path;
+    /// use electron_hook::sandbox::FlatpakInfo;
+    ///
+    /// let info = FlatpakInfo::parse(
+    ///     "[Application]\nname=dev.example.Launcher\n\n[Instance]\napp-path=/var/lib/flatpak/app/dev.example.Launcher/x86_64/stable/abc/files\nruntime-path=/var/lib/flatpak/runtime/org.freedesktop.Platform/x86_64/24.08/def/files\n",
+    /// );
+    ///
+    /// let host_path = |path: &str| info.host_path(Path::new(path));
+    ///
+    /// assert_eq!(
+    ///     host_path("/app/lib/liblauncher.so").unwrap(),
+    ///     Path::new("/var/lib/flatpak/app/dev.example.Launcher/x86_64/stable/abc/files/lib/liblauncher.so"),
+    /// );
+    /// assert_eq!(host_path("/run/host/usr/lib/libfoo.so").unwrap(), Path::new("/usr/lib/libfoo.so"));
+    /// assert_eq!(host_path("/home/me/mods/index.js").unwrap(), Path::new("/home/me/mods/index.js"));
+    /// assert_eq!(host_path("/tmp/foo"), None);
+    /// ```
+    pub fn host_path(&self, path: &Path) -> Option<PathBuf> {
+        if !path.is_absolute() {
+            return None;
+        }
+
+        let path = normalize(path);
+
+        if let Ok(rest) = path.strip_prefix("/run/host") {
+            return Some(Path::new("/").join(rest));
+        }
+
+        if let Ok(rest) = path.strip_prefix("/app") {
+            return self.app_path.as_ref().map(|app_path| app_path.join(rest));
+        }
+
+        if let Ok(rest) = path.strip_prefix("/usr") {
+            return self
+                .runtime_path
+                .as_ref()
+                .map(|runtime_path| runtime_path.join(rest));
+        }
+
+        // Our /var/{cache,config,data} are really in ~/.var/app/<id>. Shared directories like /var/home are
+        // mounted at the same path as on the host.
+        if let Ok(rest) = path.strip_prefix("/var") {
+            let first = rest.components().next().map(|first| first.as_os_str());
+
+            if let Some(first @ ("cache" | "config" | "data" | "tmp")) =
+                first.and_then(|f| f.to_str())
+            {
+                let app_dir = dirs::home_dir()?.join(".var/app").join(&self.app_id);
+
+                return match first {
+                    "tmp" => Some(app_dir.join("cache").join(rest)),
+                    _ => Some(app_dir.join(rest)),
+                };
+            }
+        }
+
+        match locate(&path) {
+            Location::Shared(path) if !path.starts_with("/tmp") => Some(path),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> FlatpakInfo {
+        FlatpakInfo {
+            app_id: "dev.example.Launcher".into(),
+            app_path: Some("/var/lib/flatpak/app/dev.example.Launcher/current/active/files".into()),
+            runtime_path: Some(
+                "/var/lib/flatpak/runtime/org.freedesktop.Platform/active/files".into(),
+            ),
+        }
+    }
+
+    #[test]
+    fn normalize_paths() {
+        let cases = [
+            ("/usr/lib/libfoo.so", "/usr/lib/libfoo.so"),
+            ("/usr//lib/./libfoo.so", "/usr/lib/libfoo.so"),
+            ("/usr/lib/../lib64/libfoo.so", "/usr/lib64/libfoo.so"),
+            ("/../../etc", "/etc"),
+            ("/var/home/me/", "/var/home/me"),
+            ("mods/../index.js", "index.js"),
+            ("./index.js", "index.js"),
+            ("/", "/"),
+        ];
+
+        for (path, expected) in cases {
+            assert_eq!(normalize(Path::new(path)), Path::new(expected), "{path}");
+        }
+    }
+
+    #[test]
+    fn locate_paths() {
+        let cases = [
+            (
+                "/usr/lib/libfoo.so",
+                Location::HostOs("/run/host/usr/lib/libfoo.so".into()),
+            ),
+            (
+                "/etc/hosts",
+                Location::HostEtc("/run/host/etc/hosts".into()),
+            ),
+            (
+                "/var/home/me/mods",
+                Location::Shared("/var/home/me/mods".into()),
+            ),
+            (
+                "/var/mnt/games/mods",
+                Location::Shared("/var/mnt/games/mods".into()),
+            ),
+            (
+                "/mnt/games/mods",
+                Location::Shared("/mnt/games/mods".into()),
+            ),
+            ("/tmp/mod.asar", Location::Shared("/tmp/mod.asar".into())),
+            (
+                "/var/../tmp/mod.asar",
+                Location::Shared("/tmp/mod.asar".into()),
+            ),
+            ("/var/lib/foo", Location::Private),
+            ("/run/user/1000/foo", Location::Private),
+            ("/usrlocal/foo", Location::Shared("/usrlocal/foo".into())),
+            ("/", Location::Private),
+            ("mods/index.js", Location::Private),
+        ];
+
+        for (path, expected) in cases {
+            assert_eq!(locate(Path::new(path)), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn host_paths() {
+        let info = info();
+        let home = dirs::home_dir().unwrap();
+        let app_data = home.join(".var/app/dev.example.Launcher");
+
+        let cases = [
+            (
+                "/app/lib/liblauncher.so",
+                Some(PathBuf::from("/var/lib/flatpak/app/dev.example.Launcher/current/active/files/lib/liblauncher.so")),
+            ),
+            (
+                "/usr/lib/libfoo.so",
+                Some(PathBuf::from("/var/lib/flatpak/runtime/org.freedesktop.Platform/active/files/lib/libfoo.so")),
+            ),
+            ("/run/host/usr/lib/libfoo.so", Some(PathBuf::from("/usr/lib/libfoo.so"))),
+            ("/var/cache/electron-hook/a.asar", Some(app_data.join("cache/electron-hook/a.asar"))),
+            ("/var/data/mods", Some(app_data.join("data/mods"))),
+            ("/var/tmp/foo", Some(app_data.join("cache/tmp/foo"))),
+            ("/var/home/me/mods/index.js", Some(PathBuf::from("/var/home/me/mods/index.js"))),
+            ("/var/mnt/games/mods", Some(PathBuf::from("/var/mnt/games/mods"))),
+            ("/mnt/games/mods", Some(PathBuf::from("/mnt/games/mods"))),
+            ("/home/me/./mods/../index.js", Some(PathBuf::from("/home/me/index.js"))),
+            // Our /tmp is private to the sandbox.
+            ("/tmp/foo", None),
+            ("/var/lib/foo", None),
+            ("/run/user/1000/bus", None),
+            ("index.js", None),
+        ];
+
+        for (path, expected) in cases {
+            assert_eq!(info.host_path(Path::new(path)), expected, "{path}");
+        }
+
+        // Without the instance paths, /app and /usr can't be translated.
+        let info = FlatpakInfo::default();
+        assert_eq!(info.host_path(Path::new("/app/lib/liblauncher.so")), None);
+        assert_eq!(info.host_path(Path::new("/usr/lib/libfoo.so")), None);
+    }
+
+    #[test]
+    fn filesystem_args() {
+        let args = |exposed: &[(&str, Access)]| {
+            let mut sandbox = SandboxPaths::new();
+
+            for (path, access) in exposed {
+                sandbox.expose(Path::new(path), *access);
+            }
+
+            sandbox.filesystem_args()
+        };
+
+        // What is exposed, and the arguments that should come out of it.
+        type Case<'a> = (&'a [(&'a str, Access)], &'a [&'a str]);
+
+        let cases: &[Case] = &[
+            (&[], &[]),
+            (
+                &[
+                    ("/usr/lib/libfoo.so", Access::ReadOnly),
+                    ("/etc/foo", Access::ReadOnly),
+                ],
+                &["--filesystem=host-os:ro", "--filesystem=host-etc:ro"],
+            ),
+            // Nested grants are covered by their parent.
+            (
+                &[
+                    (
+                        "/var/home/me/.cache/electron-hook/asar/mod.asar",
+                        Access::ReadOnly,
+                    ),
+                    ("/var/home/me/.cache/electron-hook", Access::ReadOnly),
+                    ("/var/home/me/.cache/electron-hook/asar", Access::ReadOnly),
+                ],
+                &["--filesystem=/var/home/me/.cache/electron-hook:ro"],
+            ),
+            // But not if the child needs more access than the parent has.
+            (
+                &[
+                    ("/mnt/games", Access::ReadOnly),
+                    ("/mnt/games/profile", Access::Create),
+                ],
+                &[
+                    "--filesystem=/mnt/games:ro",
+                    "--filesystem=/mnt/games/profile:create",
+                ],
+            ),
+            (
+                &[
+                    ("/mnt/games", Access::Create),
+                    ("/mnt/games/mods", Access::ReadOnly),
+                ],
+                &["--filesystem=/mnt/games:create"],
+            ),
+            // Siblings sharing a prefix are not nested.
+            (
+                &[
+                    ("/tmp/mods", Access::ReadOnly),
+                    ("/tmp/mods-old", Access::ReadOnly),
+                ],
+                &["--filesystem=/tmp/mods:ro", "--filesystem=/tmp/mods-old:ro"],
+            ),
+            // Duplicates are only granted once.
+            (
+                &[
+                    ("/tmp/mod.asar", Access::ReadOnly),
+                    ("/tmp/mod.asar", Access::ReadOnly),
+                ],
+                &["--filesystem=/tmp/mod.asar:ro"],
+            ),
+            // Unreachable and relative paths are never granted.
+            (
+                &[
+                    ("/run/user/1000/mod.asar", Access::ReadOnly),
+                    ("mod.asar", Access::ReadOnly),
+                ],
+                &[],
+            ),
+        ];
+
+        for (exposed, expected) in cases {
+            assert_eq!(args(exposed), *expected, "{exposed:?}");
+        }
+    }
+
+    #[test]
+    fn parse_flatpak_info() {
+        let info = FlatpakInfo::parse(
+            "[Application]\nname = dev.example.Launcher\nruntime=runtime/org.freedesktop.Platform/x86_64/24.08\n\n\
+             [Instance]\n  app-path=/var/lib/flatpak/app/dev.example.Launcher/current/active/files\n\
+             runtime-path=/var/lib/flatpak/runtime/org.freedesktop.Platform/active/files\nname=ignored\n",
+        );
+
+        assert_eq!(info, self::info());
+
+        assert_eq!(FlatpakInfo::parse(""), FlatpakInfo::default());
+
+        // Keys only count in their own section.
+        let info = FlatpakInfo::parse(
+            "[Instance]\nname=dev.example.Other\n[Application]\napp-path=/foo\n",
+        );
+        assert_eq!(info, FlatpakInfo::default());
+    }
+}
@@ -0,0 +1,299 @@
+use std::path::{Path, PathBuf};
+
+use crate::{Error, Result};
+
+use super::discovery::scan_dir;
+use super::flatpak::path_hash;
+
+/// An installed snap, and where its Electron app lives inside it.
+pub(crate) struct Snap {
+    pub(crate) name: String,
+    /// The Electron executable, relative to `$SNAP`.
+    pub(crate) executable: PathBuf,
+    /// Whether the snap uses classic (or dev mode) confinement, and can see the whole filesystem.
+    pub(crate) unconfined: bool,
+}
+
+impl Snap {
+    /// Find the snap called `name` and the Electron app inside it.
+    pub(crate) fn find(name: &str) -> Result<Self> {
+        let snap_dir = Path::new("/snap").join(name).join("current");
+
+        if !snap_dir.is_dir() {
+            return Err(Error::SnapNotInstalled(name.to_string()));
+        }
+
+        // Electron apps in snaps are usually in `usr/share/<name>` or `opt/<name>`, so look a bit deeper.
+        let executable = ["usr/share", "usr/lib", "opt", "."]
+            .iter()
+            .flat_map(|dir| scan_dir(&snap_dir.join(dir)))
+            .find_map(|candidate| candidate.executable)
+            .ok_or_else(|| Error::ExecutableNotFound(snap_dir.clone()))?;
+
+        let executable = executable
+            .strip_prefix(&snap_dir)
+            .map_err(|_| Error::InvalidPath(executable.clone()))?
+            .to_path_buf();
+
+        let confinement = std::fs::read_to_string(snap_dir.join("meta/snap.yaml"))
+            .unwrap_or_default()
+            .lines()
+            .find_map(|line| line.strip_prefix("confinement:"))
+            .map(|confinement| confinement.trim().to_string())
+            .unwrap_or_default();
+
+        Ok(Self {
+            name: name.to_string(),
+            executable,
+            unconfined: confinement == "classic" || confinement == "devmode",
+        })
+    }
+
+    /// The app's `resources` directory, as the app sees it.
+    ///
+    /// `$SNAP` is the current revision, e.g. `/snap/discord/245`, rather than `/snap/discord/current`.
+    pub(crate) fn resources_dir(&self) -> PathBuf {
+        let snap_dir = Path::new("/snap").join(&self.name).join("current");
+        let snap_dir = snap_dir.canonicalize().unwrap_or(snap_dir);
+
+        let app_dir = self.executable.parent().unwrap_or(Path::new(""));
+
+        snap_dir.join(app_dir).join("resources")
+    }
+
+    /// The snap's `$SNAP_USER_COMMON`, which it can always read and write.
+    fn user_common_dir(&self) -> Result<PathBuf> {
+        let home = dirs::home_dir().ok_or_else(|| self.unreachable(Path::new("~")))?;
+
+        Ok(home.join("snap").join(&self.name).join("common"))
+    }
+
+    /// The folder that files are copied to, so the snap can read them.
+    fn staging_dir(&self) -> Result<PathBuf> {
+        crate::paths::ensure_dir(self.user_common_dir()?.join("electron-hook"))
+            .map_err(|_| self.unreachable(Path::new("electron-hook")))
+    }
+
+    fn unreachable(&self, path: &Path) -> Error {
+        Error::SnapConfinement {
+            name: self.name.clone(),
+            path: path.into(),
+        }
+    }
+
+    /// Whether the snap can see `path` under strict confinement.
+    fn can_see(&self, path: &Path) -> bool {
+        self.can_see_from(dirs::home_dir().as_deref(), path)
+    }
+
+    /// Like [Snap::can_see], for the home directory `home`.
+    ///
+    /// The `home` interface only gives access to non-hidden files in the home directory, apart from other
+    /// snaps' data in `~/snap`, and the snap can always see its own user data directory.
+    fn can_see_from(&self, home: Option<&Path>, path: &Path) -> bool {
+        if self.unconfined {
+            return true;
+        }
+
+        let Some(home) = home else {
+            return false;
+        };
+
+        if path.starts_with(home.join("snap").join(&self.name)) {
+            return true;
+        }
+
+        path.strip_prefix(home)
+            .ok()
+            .and_then(|rest| rest.components().next())
+            .is_some_and(|first| {
+                let first = first.as_os_str().to_string_lossy();
+                !first.starts_with('.') && first != "snap"
+            })
+    }
+
+    /// Copy a file somewhere the snap can read it, unless it already can.
+    pub(crate) fn expose_file(&self, path: &Path) -> Result<PathBuf> {
+        if self.can_see(path) {
+            return Ok(path.to_path_buf());
+        }
+
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| Error::InvalidPath(path.into()))?;
+
+        let staged = staging_dir_for(&self.staging_dir()?, path).join(file_name);
+
+        stage_file(path, &staged).map_err(|_| self.unreachable(path))?;
+
+        Ok(staged)
+    }
+
+    /// Copy the directory containing a file somewhere the snap can read it, unless it already can.
+    ///
+    /// This is used for the mod entrypoint, which may load other files next to it.
+    pub(crate) fn expose_in_dir(&self, path: &Path) -> Result<PathBuf> {
+        if self.can_see(path) {
+            return Ok(path.to_path_buf());
+        }
+
+        let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+            return Err(Error::InvalidPath(path.into()));
+        };
+
+        let dir_name = dir
+            .file_name()
+            .ok_or_else(|| Error::InvalidPath(dir.into()))?;
+
+        let staged = staging_dir_for(&self.staging_dir()?, dir).join(dir_name);
+
+        stage_dir(dir, &staged).map_err(|_| self.unreachable(dir))?;
+
+        Ok(staged.join(file_name))
+    }
+
+    /// Check that the snap can use `dir`, since directories it writes to are never copied.
+    pub(crate) fn expose_dir(&self, dir: &Path) -> Result<PathBuf> {
+        match self.can_see(dir) {
+            true => Ok(dir.to_path_buf()),
+            false => Err(self.unreachable(dir)),
+        }
+    }
+}
+
+/// Where `path` is staged in `staging_dir`: a directory named after a hash of its full path, like for
+/// Flatpaks, so files and directories with the same name from different places don't overwrite each other.
+fn staging_dir_for(staging_dir: &Path, path: &Path) -> PathBuf {
+    staging_dir.join(format!("{:016x}", path_hash(path)))
+}
+
+fn stage_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(dir) = to.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    std::fs::copy(from, to).map(|_| ())
+}
+
+/// Copy `from` to `to`, replacing what was staged there before, so files removed since are gone too.
+fn stage_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    match std::fs::remove_dir_all(to) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+
+    copy_dir(from, to)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Quote `value` for use in a POSIX shell script.
+pub(crate) fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snap(unconfined: bool) -> Snap {
+        Snap {
+            name: "discord".into(),
+            executable: "usr/share/discord/Discord".into(),
+            unconfined,
+        }
+    }
+
+    #[test]
+    fn can_see_non_hidden_home_files() {
+        let home = Some(Path::new("/home/CoolPerson"));
+        let strict = snap(false);
+
+        assert!(strict.can_see_from(home, Path::new("/home/CoolPerson/mods/mod.asar")));
+        assert!(strict.can_see_from(
+            home,
+            Path::new("/home/CoolPerson/snap/discord/common/x.asar")
+        ));
+
+        assert!(!strict.can_see_from(home, Path::new("/home/CoolPerson/.cache/mod.asar")));
+        assert!(!strict.can_see_from(home, Path::new("/home/CoolPerson/snap/slack/common/x.asar")));
+        assert!(!strict.can_see_from(home, Path::new("/home/CoolPerson")));
+        assert!(!strict.can_see_from(home, Path::new("/tmp/mod.asar")));
+        assert!(!strict.can_see_from(None, Path::new("/home/CoolPerson/mods/mod.asar")));
+
+        assert!(snap(true).can_see_from(home, Path::new("/tmp/mod.asar")));
+    }
+
+    #[test]
+    fn staging_keeps_same_names_apart_and_drops_stale_files() {
+        let root = std::env::temp_dir().join(format!("electron-hook-snap-{}", std::process::id()));
+        let staging_dir = root.join("staging");
+
+        for name in ["a", "b"] {
+            std::fs::create_dir_all(root.join(name).join("dist")).unwrap();
+            std::fs::write(root.join(name).join("dist/index.js"), name).unwrap();
+            std::fs::write(root.join(name).join("app.asar"), name).unwrap();
+        }
+
+        let staged_a = staging_dir_for(&staging_dir, &root.join("a/app.asar")).join("app.asar");
+        let staged_b = staging_dir_for(&staging_dir, &root.join("b/app.asar")).join("app.asar");
+        stage_file(&root.join("a/app.asar"), &staged_a).unwrap();
+        stage_file(&root.join("b/app.asar"), &staged_b).unwrap();
+
+        assert_ne!(staged_a, staged_b);
+        assert_eq!(std::fs::read(&staged_a).unwrap(), b"a");
+        assert_eq!(std::fs::read(&staged_b).unwrap(), b"b");
+
+        let staged_dir = staging_dir_for(&staging_dir, &root.join("a/dist")).join("dist");
+        std::fs::write(root.join("a/dist/old.js"), "old").unwrap();
+        stage_dir(&root.join("a/dist"), &staged_dir).unwrap();
+        assert!(staged_dir.join("old.js").is_file());
+
+        // An update removed `old.js`.
+        std::fs::remove_file(root.join("a/dist/old.js")).unwrap();
+        stage_dir(&root.join("a/dist"), &staged_dir).unwrap();
+        assert!(!staged_dir.join("old.js").exists());
+        assert_eq!(std::fs::read(staged_dir.join("index.js")).unwrap(), b"a");
+
+        let staged_b_dir = staging_dir_for(&staging_dir, &root.join("b/dist")).join("dist");
+        assert_ne!(staged_dir, staged_b_dir);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn shell_quote_survives_the_shell() {
+        for value in [
+            "plain",
+            "with space",
+            "it's",
+            "$HOME `id` \"x\"",
+            "",
+            "a\nb",
+        ] {
+            let output = std::process::Command::new("sh")
+                .args(["-c", &format!("printf %s {}", shell_quote(value))])
+                .output()
+                .unwrap();
+
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), value);
+        }
+
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+}
@@ -0,0 +1,418 @@
+//! Opt-in tracing of hooked calls
+//!
+//! Set `MODLOADER_TRACE` to a directory, and every process writes each intercepted call to
+//! `trace-<pid>.log` in it, followed by a summary when it exits.
+//!
+//! This runs inside hooked libc functions, so it never allocates or calls anything we hook: entries are
+//! formatted into stack buffers, the log file is opened with a raw syscall, and written with `write`.
+
+use std::ffi::{c_char, CStr};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+/// The hooked functions, for counting calls.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Hook {
+    UvFsLstat,
+    UnsetEnv,
+    XStat64,
+    LXStat64,
+    Stat,
+    Stat64,
+    LStat,
+    LStat64,
+    FStatAt,
+    FStatAt64,
+    Statx,
+    Access,
+    Open,
+    Open64,
+    OpenAt,
+    OpenAt64,
+    Execve,
+    Execv,
+    Execvp,
+    Execvpe,
+    PosixSpawn,
+    PosixSpawnp,
+}
+
+impl Hook {
+    const ALL: [Hook; 22] = [
+        Hook::UvFsLstat,
+        Hook::UnsetEnv,
+        Hook::XStat64,
+        Hook::LXStat64,
+        Hook::Stat,
+        Hook::Stat64,
+        Hook::LStat,
+        Hook::LStat64,
+        Hook::FStatAt,
+        Hook::FStatAt64,
+        Hook::Statx,
+        Hook::Access,
+        Hook::Open,
+        Hook::Open64,
+        Hook::OpenAt,
+        Hook::OpenAt64,
+        Hook::Execve,
+        Hook::Execv,
+        Hook::Execvp,
+        Hook::Execvpe,
+        Hook::PosixSpawn,
+        Hook::PosixSpawnp,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Hook::UvFsLstat => "uv_fs_lstat",
+            Hook::UnsetEnv => "unsetenv",
+            Hook::XStat64 => "__xstat64",
+            Hook::LXStat64 => "__lxstat64",
+            Hook::Stat => "stat",
+            Hook::Stat64 => "stat64",
+            Hook::LStat => "lstat",
+            Hook::LStat64 => "lstat64",
+            Hook::FStatAt => "fstatat",
+            Hook::FStatAt64 => "fstatat64",
+            Hook::Statx => "statx",
+            Hook::Access => "access",
+            Hook::Open => "open",
+            Hook::Open64 => "open64",
+            Hook::OpenAt => "openat",
+            Hook::OpenAt64 => "openat64",
+            Hook::Execve => "execve",
+            Hook::Execv => "execv",
+            Hook::Execvp => "execvp",
+            Hook::Execvpe => "execvpe",
+            Hook::PosixSpawn => "posix_spawn",
+            Hook::PosixSpawnp => "posix_spawnp",
+        }
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+static CALLS: [AtomicU64; Hook::ALL.len()] = [ZERO; Hook::ALL.len()];
+static REDIRECTS: [AtomicU64; Hook::ALL.len()] = [ZERO; Hook::ALL.len()];
+
+const UNKNOWN: u8 = 0;
+const ENABLED: u8 = 1;
+const DISABLED: u8 = 2;
+
+static STATE: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+/// Whether `MODLOADER_TRACE` is set. This is checked once, since it's on the path of every hooked call.
+pub(crate) fn enabled() -> bool {
+    match STATE.load(Ordering::Relaxed) {
+        ENABLED => true,
+        DISABLED => false,
+        _ => {
+            let enabled = !unsafe { libc::getenv(c"MODLOADER_TRACE".as_ptr()) }.is_null();
+            STATE.store(if enabled { ENABLED } else { DISABLED }, Ordering::Relaxed);
+            enabled
+        }
+    }
+}
+
+/// The log file of the current process, as its PID in the high half and the file descriptor in the low half.
+///
+/// Chromium forks its child processes from a zygote without exec'ing, so this is reopened whenever the
+/// PID changes. It's an atomic rather than a lock, since a forked child would wait forever on a lock that
+/// another thread of its parent held at the time, and never get to exec.
+static LOG: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// The `--type` of the process [LOG] belongs to, and its length.
+///
+/// Every thread that sees a new PID writes the same bytes here before publishing the new [LOG].
+#[allow(clippy::declare_interior_mutable_const)]
+const NUL: AtomicU8 = AtomicU8::new(0);
+static PROCESS_TYPE: [AtomicU8; 32] = [NUL; 32];
+static PROCESS_TYPE_LEN: AtomicUsize = AtomicUsize::new(0);
+
+fn pack(pid: i32, fd: i32) -> u64 {
+    (pid as u32 as u64) << 32 | fd as u32 as u64
+}
+
+fn unpack(log: u64) -> (i32, i32) {
+    ((log >> 32) as u32 as i32, log as u32 as i32)
+}
+
+/// A fixed-size buffer to format into, which silently truncates instead of allocating.
+struct StackBuf<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> StackBuf<N> {
+    fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+        }
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        let len = bytes.len().min(N - self.len);
+        self.buf[self.len..self.len + len].copy_from_slice(&bytes[..len]);
+        self.len += len;
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl<const N: usize> Write for StackBuf<N> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.push_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// The log file of the current process, opening a new one after a fork.
+fn log_for_current_process() -> Option<(i32, i32)> {
+    let pid = unsafe { libc::getpid() };
+    let mut current = LOG.load(Ordering::Acquire);
+
+    if unpack(current).0 != pid {
+        // The parent's file stays open in a forked child, but it isn't ours to write to.
+        let mut cmdline = [0u8; 4096];
+        let process_type = process_type(read_cmdline(&mut cmdline));
+
+        for (i, byte) in process_type.iter().take(PROCESS_TYPE.len()).enumerate() {
+            PROCESS_TYPE[i].store(*byte, Ordering::Relaxed);
+        }
+        PROCESS_TYPE_LEN.store(
+            process_type.len().min(PROCESS_TYPE.len()),
+            Ordering::Relaxed,
+        );
+
+        let fd = unsafe { open_log(pid) };
+
+        current =
+            match LOG.compare_exchange(current, pack(pid, fd), Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => pack(pid, fd),
+                // Another thread got there first.
+                Err(actual) => {
+                    if fd >= 0 {
+                        unsafe { libc::syscall(libc::SYS_close, fd) };
+                    }
+                    actual
+                }
+            };
+    }
+
+    let (log_pid, fd) = unpack(current);
+    (log_pid == pid && fd >= 0).then_some((pid, fd))
+}
+
+/// Read `/proc/self/cmdline` into `buf` with raw syscalls, returning the part that was read.
+fn read_cmdline(buf: &mut [u8]) -> &[u8] {
+    let len = unsafe {
+        let fd = libc::syscall(
+            libc::SYS_openat,
+            libc::AT_FDCWD,
+            c"/proc/self/cmdline".as_ptr(),
+            libc::O_RDONLY | libc::O_CLOEXEC,
+        ) as i32;
+
+        if fd < 0 {
+            0
+        } else {
+            let len = libc::syscall(libc::SYS_read, fd, buf.as_mut_ptr(), buf.len());
+            libc::syscall(libc::SYS_close, fd);
+            len.max(0) as usize
+        }
+    };
+
+    &buf[..len]
+}
+
+/// The `--type` Chromium passes its child processes. The main process has none.
+fn process_type(cmdline: &[u8]) -> &[u8] {
+    cmdline
+        .split(|&byte| byte == 0)
+        .find_map(|arg| arg.strip_prefix(b"--type="))
+        .unwrap_or(b"browser")
+}
+
+/// Open `$MODLOADER_TRACE/trace-<pid>.log` with a raw syscall, so our own `open` hooks aren't involved.
+unsafe fn open_log(pid: i32) -> i32 {
+    let dir = libc::getenv(c"MODLOADER_TRACE".as_ptr());
+    if dir.is_null() {
+        return -1;
+    }
+
+    let mut path = StackBuf::<4096>::new();
+    path.push_bytes(CStr::from_ptr(dir).to_bytes());
+    let _ = write!(path, "/trace-{pid}.log");
+
+    if path.len == path.buf.len() {
+        return -1;
+    }
+
+    path.push_bytes(b"\0");
+
+    libc::syscall(
+        libc::SYS_openat,
+        libc::AT_FDCWD,
+        path.buf.as_ptr(),
+        libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND | libc::O_CLOEXEC,
+        0o644,
+    ) as i32
+}
+
+fn write_all(fd: i32, mut bytes: &[u8]) {
+    while !bytes.is_empty() {
+        let written = unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) };
+        if written <= 0 {
+            return;
+        }
+        bytes = &bytes[written as usize..];
+    }
+}
+
+/// Record an intercepted call.
+///
+/// `result` is what the real function returned. For functions that set `errno`, it is included when the
+/// call failed, so this must be called before anything else can change it.
+pub(crate) fn record(hook: Hook, path: *const c_char, redirect_to: Option<&CStr>, result: i64) {
+    let errno = unsafe { *libc::__errno_location() };
+
+    CALLS[hook as usize].fetch_add(1, Ordering::Relaxed);
+    if redirect_to.is_some() {
+        REDIRECTS[hook as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    if enabled() {
+        write_entry(hook, path, redirect_to, result, errno);
+    }
+
+    // Leave errno as the real function set it.
+    unsafe { *libc::__errno_location() = errno };
+}
+
+fn write_entry(
+    hook: Hook,
+    path: *const c_char,
+    redirect_to: Option<&CStr>,
+    result: i64,
+    errno: i32,
+) {
+    let Some((pid, fd)) = log_for_current_process() else {
+        return;
+    };
+
+    let tid = unsafe { libc::syscall(libc::SYS_gettid) };
+
+    let mut entry = StackBuf::<8192>::new();
+
+    let _ = write!(entry, "[{pid}:{tid} ");
+
+    let process_type_len = PROCESS_TYPE_LEN.load(Ordering::Relaxed);
+    for byte in &PROCESS_TYPE[..process_type_len] {
+        entry.push_bytes(&[byte.load(Ordering::Relaxed)]);
+    }
+
+    let _ = write!(entry, "] {} ", hook.name());
+
+    match path.is_null() {
+        true => entry.push_bytes(b"(null)"),
+        false => entry.push_bytes(unsafe { CStr::from_ptr(path) }.to_bytes()),
+    }
+
+    if let Some(redirect_to) = redirect_to {
+        entry.push_bytes(b" -> ");
+        entry.push_bytes(redirect_to.to_bytes());
+    }
+
+    let _ = write!(entry, " = {result}");
+
+    if result < 0 && errno != 0 {
+        let _ = write!(entry, " (errno {errno})");
+    }
+
+    entry.push_bytes(b"\n");
+
+    write_all(fd, entry.as_bytes());
+}
+
+/// Write how often each hook was called to the log when the process exits.
+#[ctor::dtor]
+fn write_summary() {
+    if !enabled() {
+        return;
+    }
+
+    let Some((pid, fd)) = log_for_current_process() else {
+        return;
+    };
+
+    let mut summary = StackBuf::<2048>::new();
+    let _ = write!(summary, "[{pid}] summary:");
+
+    for hook in Hook::ALL {
+        let calls = CALLS[hook as usize].load(Ordering::Relaxed);
+        let redirects = REDIRECTS[hook as usize].load(Ordering::Relaxed);
+
+        if calls > 0 {
+            let _ = write!(summary, " {}={calls} ({redirects} redirected)", hook.name());
+        }
+    }
+
+    summary.push_bytes(b"\n");
+
+    write_all(fd, summary.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stack_buf_truncates() {
+        let mut buf = StackBuf::<8>::new();
+
+        let _ = write!(buf, "trace-{}", 123);
+        assert_eq!(buf.as_bytes(), b"trace-12");
+
+        buf.push_bytes(b".log");
+        assert_eq!(buf.as_bytes(), b"trace-12");
+        assert_eq!(buf.len, 8);
+    }
+
+    #[test]
+    fn process_type_reads_chromium_flags() {
+        assert_eq!(
+            process_type(b"/opt/discord/Discord\0--no-sandbox\0"),
+            b"browser"
+        );
+        assert_eq!(
+            process_type(b"/proc/self/exe\0--type=renderer\0--lang=en-US\0"),
+            b"renderer"
+        );
+        assert_eq!(
+            process_type(b"/opt/discord/Discord\0--type=zygote"),
+            b"zygote"
+        );
+        assert_eq!(process_type(b""), b"browser");
+
+        // The test runner has no `--type`.
+        let mut cmdline = [0u8; 4096];
+        let cmdline = read_cmdline(&mut cmdline);
+        assert!(!cmdline.is_empty());
+        assert_eq!(process_type(cmdline), b"browser");
+    }
+
+    #[test]
+    fn log_is_per_process() {
+        let (pid, fd) = (unsafe { libc::getpid() }, 42);
+
+        assert_eq!(unpack(pack(pid, fd)), (pid, fd));
+        assert_eq!(unpack(pack(pid, -1)), (pid, -1));
+        assert_ne!(unpack(u64::MAX).0, pid);
+    }
+}
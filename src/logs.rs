@@ -0,0 +1,52 @@
+//! Per-instance log files
+
+use std::path::PathBuf;
+
+use crate::{paths::log_dir, Error, Result};
+
+/// Limits for the log files kept for each ASAR ID.
+///
+/// Every launch writes to a new file, which is rolled over to another new file once it reaches `max_size`.
+/// Whenever a log file is started, the oldest ones are deleted until at most `max_files` are left, so the
+/// logs take up about `max_size * max_files` bytes at most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogRotation {
+    /// The size in bytes at which a log file is rolled over to a new one.
+    pub max_size: u64,
+    /// The maximum number of log files to keep, including the one being written.
+    pub max_files: usize,
+}
+
+impl Default for LogRotation {
+    fn default() -> Self {
+        Self {
+            max_size: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+/// Create a new log file for `asar_id`, named after the current time.
+///
+/// Linux: `/home/CoolPerson/.cache/electron-hook/logs/{asar_id}/{timestamp}.log`
+pub(crate) fn create(asar_id: &str) -> Result<(PathBuf, std::fs::File)> {
+    let dir = log_dir(asar_id)?;
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    let path = dir.join(format!("{timestamp}.log"));
+
+    let file = std::fs::File::options()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|source| Error::Log {
+            path: path.clone(),
+            source,
+        })?;
+
+    Ok((path, file))
+}
@@ -1,50 +1,77 @@
 //! Path utilities
 
-fn cache_dir() -> std::path::PathBuf {
+use crate::{Error, Result};
+
+fn cache_dir() -> Result<std::path::PathBuf> {
     dirs::cache_dir()
-        .expect("Failed to get cache directory")
-        .join("electron-hook")
+        .map(|dir| dir.join("electron-hook"))
+        .ok_or(Error::CacheDirUnavailable)
 }
 
-fn asar_cache_dir() -> std::path::PathBuf {
-    ensure_dir(cache_dir().join("asar"))
+fn asar_cache_dir() -> Result<std::path::PathBuf> {
+    ensure_dir(cache_dir()?.join("asar"))
 }
 
 /// The path to a specific .asar file
-pub fn asar_cache_path(asar_id: &str) -> std::path::PathBuf {
-    asar_cache_dir().join(format!("{asar_id}.asar"))
+pub fn asar_cache_path(asar_id: &str) -> Result<std::path::PathBuf> {
+    Ok(asar_cache_dir()?.join(format!("{asar_id}.asar")))
 }
 
-fn mod_artifacts_dir() -> std::path::PathBuf {
-    ensure_dir(cache_dir().join("mods"))
+fn mod_artifacts_dir() -> Result<std::path::PathBuf> {
+    ensure_dir(cache_dir()?.join("mods"))
 }
 
 /// The path to a specific mod artifact folder
-pub fn mod_artifact_dir(mod_name: &str) -> std::path::PathBuf {
-    mod_artifacts_dir().join(mod_name)
+pub fn mod_artifact_dir(mod_name: &str) -> Result<std::path::PathBuf> {
+    Ok(mod_artifacts_dir()?.join(mod_name))
+}
+
+fn logs_dir() -> Result<std::path::PathBuf> {
+    ensure_dir(cache_dir()?.join("logs"))
+}
+
+/// The path to the log folder for a specific ASAR ID
+pub fn log_dir(asar_id: &str) -> Result<std::path::PathBuf> {
+    ensure_dir(logs_dir()?.join(asar_id))
+}
+
+/// The folder that files are copied to when a sandboxed app can't reach them where they are.
+pub(crate) fn sandbox_staging_dir() -> Result<std::path::PathBuf> {
+    ensure_dir(cache_dir()?.join("sandbox"))
+}
+
+/// The folder that AppImages are extracted to when FUSE is unavailable.
+pub(crate) fn appimage_extract_dir() -> Result<std::path::PathBuf> {
+    ensure_dir(cache_dir()?.join("appimage"))
+}
+
+/// The script that runs the ASAR template with `NODE_OPTIONS=--require`.
+pub(crate) fn require_script_path(asar_id: &str) -> Result<std::path::PathBuf> {
+    Ok(ensure_dir(cache_dir()?.join("require"))?.join(format!("{asar_id}.js")))
 }
 
-fn data_dir() -> std::path::PathBuf {
+fn data_dir() -> Result<std::path::PathBuf> {
     dirs::data_dir()
-        .expect("Failed to get data directory")
-        .join("electron-hook")
+        .map(|dir| dir.join("electron-hook"))
+        .ok_or(Error::DataDirUnavailable)
 }
 
-fn data_profiles_dir() -> std::path::PathBuf {
-    ensure_dir(data_dir().join("profiles"))
+fn data_profiles_dir() -> Result<std::path::PathBuf> {
+    ensure_dir(data_dir()?.join("profiles"))
 }
 
 /// The path to a specific profile directory
-pub fn data_profile_dir(profile_id: &str) -> std::path::PathBuf {
-    data_profiles_dir().join(profile_id)
+pub fn data_profile_dir(profile_id: &str) -> Result<std::path::PathBuf> {
+    Ok(data_profiles_dir()?.join(profile_id))
 }
 
 /// Ensure a directory exists, recursively creating it if it doesn't
-pub fn ensure_dir(path: std::path::PathBuf) -> std::path::PathBuf {
+pub fn ensure_dir(path: std::path::PathBuf) -> Result<std::path::PathBuf> {
     if !path.exists() {
-        std::fs::create_dir_all(&path)
-            .map_err(|e| format!("Failed to create directory: {e}"))
-            .unwrap();
+        std::fs::create_dir_all(&path).map_err(|source| Error::CreateDir {
+            path: path.clone(),
+            source,
+        })?;
     }
-    path
+    Ok(path)
 }
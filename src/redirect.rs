@@ -0,0 +1,444 @@
+//! Path redirection rules for the Linux hooks
+//!
+//! The hooks replace the paths the app opens and stats according to a list of [Rule]s. The first rule that
+//! matches a path wins. The [Launcher](crate::Launcher) passes the rules added with
+//! [Launcher::with_redirect](crate::Launcher::with_redirect) to the app as JSON in `MODLOADER_REDIRECTS`,
+//! and the hooks add the [default rules](default_rules) after them.
+//!
+//! Paths are normalized before matching, so `/opt/discord//resources/../resources/app.asar` is matched
+//! as `/opt/discord/resources/app.asar`.
+//!
+//! ```rust
+//! use electron_hook::redirect::{redirect, Rule};
+//!
+//! let rules = [
+//!     Rule::exact("app-update.yml", "/home/CoolPerson/mods/app-update.yml").with_scope("/opt/discord/resources"),
+//!     Rule::glob("/opt/discord/resources/_app.asar", "{dir}/app.asar"),
+//!     Rule::glob("/opt/discord/resources/app.asar", "/home/CoolPerson/.cache/electron-hook/asar/moonlight.asar"),
+//! ];
+//!
+//! assert_eq!(
+//!     redirect(&rules, "/opt/discord/resources/app-update.yml").as_deref(),
+//!     Some("/home/CoolPerson/mods/app-update.yml"),
+//! );
+//! assert_eq!(
+//!     redirect(&rules, "/opt/discord/resources/_app.asar").as_deref(),
+//!     Some("/opt/discord/resources/app.asar"),
+//! );
+//! assert_eq!(
+//!     redirect(&rules, "/opt/discord/resources/app.asar").as_deref(),
+//!     Some("/home/CoolPerson/.cache/electron-hook/asar/moonlight.asar"),
+//! );
+//! assert_eq!(redirect(&rules, "/opt/discord/resources/app.asar.unpacked/foo.node"), None);
+//! ```
+
+/// How a [Rule] matches paths.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// Matches this path only.
+    Exact(String),
+    /// Matches this path and everything below it. The rest of the path is appended to the target.
+    Prefix(String),
+    /// Matches paths against a pattern, where `*` matches any number of bytes (including `/`), `?`
+    /// matches a single byte and `\` matches the next byte literally. See [glob_escape].
+    Glob(String),
+}
+
+/// A single redirection rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    /// Which paths are redirected.
+    pub source: Source,
+
+    /// Where they are redirected to.
+    ///
+    /// `{dir}` is replaced with the directory containing the matched path.
+    pub target: String,
+
+    /// The directory this rule is limited to, e.g. the app's `resources` directory.
+    ///
+    /// Relative sources and targets are resolved against it.
+    pub scope: Option<String>,
+}
+
+impl Rule {
+    fn new(source: Source, target: &str) -> Self {
+        Self {
+            source,
+            target: target.to_string(),
+            scope: None,
+        }
+    }
+
+    /// Redirect `source` to `target`.
+    ///
+    /// See [Source::Exact]
+    pub fn exact(source: &str, target: &str) -> Self {
+        Self::new(Source::Exact(source.to_string()), target)
+    }
+
+    /// Redirect everything below `source` to the same place below `target`.
+    ///
+    /// See [Source::Prefix]
+    pub fn prefix(source: &str, target: &str) -> Self {
+        Self::new(Source::Prefix(source.to_string()), target)
+    }
+
+    /// Redirect paths matching `pattern` to `target`.
+    ///
+    /// See [Source::Glob]
+    pub fn glob(pattern: &str, target: &str) -> Self {
+        Self::new(Source::Glob(pattern.to_string()), target)
+    }
+
+    /// Limit this rule to paths in `scope`.
+    ///
+    /// See [Rule::scope]
+    pub fn with_scope(mut self, scope: &str) -> Self {
+        self.scope = Some(scope.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Resolve a relative path against the scope.
+    fn scoped(&self, path: &str) -> String {
+        match &self.scope {
+            Some(scope) if !path.starts_with('/') => format!("{scope}/{path}"),
+            _ => path.to_string(),
+        }
+    }
+
+    /// The target for `path` if this rule matches it.
+    pub fn apply(&self, path: &str) -> Option<String> {
+        if let Some(scope) = &self.scope {
+            let in_scope = path
+                .strip_prefix(scope.as_str())
+                .is_some_and(|rest| rest.starts_with('/'));
+
+            if !in_scope {
+                return None;
+            }
+        }
+
+        let rest = match &self.source {
+            Source::Exact(source) => (path == self.scoped(source)).then_some(""),
+            Source::Prefix(source) => {
+                let source = self.scoped(source);
+                let source = source.trim_end_matches('/');
+
+                path.strip_prefix(source)
+                    .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+            }
+            Source::Glob(pattern) => glob_match(&self.scoped(pattern), path).then_some(""),
+        }?;
+
+        let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+        let target = self.scoped(&self.target).replace("{dir}", dir);
+
+        Some(format!("{target}{rest}"))
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let (kind, source) = match &self.source {
+            Source::Exact(source) => ("exact", source),
+            Source::Prefix(source) => ("prefix", source),
+            Source::Glob(source) => ("glob", source),
+        };
+
+        serde_json::json!({
+            "match": kind,
+            "source": source,
+            "target": self.target,
+            "scope": self.scope,
+        })
+    }
+
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        let source = value.get("source")?.as_str()?.to_string();
+
+        let source = match value.get("match")?.as_str()? {
+            "exact" => Source::Exact(source),
+            "prefix" => Source::Prefix(source),
+            "glob" => Source::Glob(source),
+            _ => return None,
+        };
+
+        Some(Self {
+            source,
+            target: value.get("target")?.as_str()?.to_string(),
+            scope: value
+                .get("scope")
+                .and_then(|scope| scope.as_str())
+                .map(str::to_string),
+        })
+    }
+}
+
+/// The `resources` directories the default rules apply to when the app's own isn't known.
+pub const ANY_RESOURCES_DIR: &str = "*/resources";
+
+/// The rules electron-hook needs to load the mod: `app.asar` is replaced with the mod's ASAR, and the
+/// original is moved to `_app.asar`.
+///
+/// `resources_dir` is a glob pattern for the app's `resources` directory. This should be the exact
+/// directory where possible, so other apps started by the modded one are left alone. Escape it with
+/// [glob_escape] if it's a path rather than a pattern.
+///
+/// ```rust
+/// use electron_hook::redirect::{default_rules, redirect};
+///
+/// let rules = default_rules("/tmp/mod.asar", "/opt/discord/resources");
+///
+/// assert_eq!(redirect(&rules, "/opt/discord/resources/app.asar").as_deref(), Some("/tmp/mod.asar"));
+/// assert_eq!(redirect(&rules, "/opt/discord/bin/../resources/app.asar").as_deref(), Some("/tmp/mod.asar"));
+/// assert_eq!(redirect(&rules, "/opt/slack/resources/app.asar"), None);
+/// assert_eq!(redirect(&rules, "/home/CoolPerson/backup/opt/discord/resources/app.asar"), None);
+/// ```
+pub fn default_rules(asar_path: &str, resources_dir: &str) -> Vec<Rule> {
+    let resources_dir = resources_dir.trim_end_matches('/');
+
+    vec![
+        Rule::glob(&format!("{resources_dir}/_app.asar"), "{dir}/app.asar"),
+        Rule::glob(&format!("{resources_dir}/app.asar"), asar_path),
+    ]
+}
+
+/// Find where `path` is redirected to by the first matching rule.
+pub fn redirect(rules: &[Rule], path: &str) -> Option<String> {
+    let path = normalize(path);
+
+    rules.iter().find_map(|rule| rule.apply(&path))
+}
+
+/// Lexically normalize a path, removing `.`, `..` and duplicate slashes.
+///
+/// ```rust
+/// use electron_hook::redirect::normalize;
+///
+/// assert_eq!(normalize("/opt//discord/./bin/../resources/"), "/opt/discord/resources");
+/// assert_eq!(normalize("/../app.asar"), "/app.asar");
+/// assert_eq!(normalize("../resources/app.asar"), "../resources/app.asar");
+/// ```
+pub fn normalize(path: &str) -> std::borrow::Cow<'_, str> {
+    let is_normal = !path.contains("//")
+        && !path.ends_with('/')
+        && path.split('/').all(|part| part != "." && part != "..");
+
+    if is_normal || path == "/" {
+        return path.into();
+    }
+
+    let absolute = path.starts_with('/');
+    let mut parts = Vec::new();
+
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => match parts.last() {
+                Some(&last) if last != ".." => {
+                    parts.pop();
+                }
+                // There is nothing above the root.
+                _ if absolute => {}
+                _ => parts.push(part),
+            },
+            _ => parts.push(part),
+        }
+    }
+
+    let normalized = parts.join("/");
+
+    match absolute {
+        true => format!("/{normalized}").into(),
+        false => normalized.into(),
+    }
+}
+
+/// Read the rules the launcher passed in `MODLOADER_REDIRECTS`, followed by the
+/// [default rules](default_rules) for `MODLOADER_ASAR_PATH`.
+///
+/// The default rules are limited to `MODLOADER_RESOURCES_DIR`. Inside an AppImage, where the launcher
+/// can't know where it will be mounted, they are limited to `$APPDIR` instead.
+///
+/// There are no rules if nothing is set, e.g. when another program inherited our `LD_PRELOAD`, so nothing
+/// is redirected.
+///
+/// ```rust
+/// use electron_hook::redirect::{from_env, redirect};
+///
+/// std::env::remove_var("MODLOADER_REDIRECTS");
+/// std::env::remove_var("MODLOADER_ASAR_PATH");
+/// assert!(from_env().is_empty());
+///
+/// std::env::set_var("MODLOADER_ASAR_PATH", "/tmp/mod.asar");
+/// std::env::set_var("MODLOADER_RESOURCES_DIR", "/opt/discord/resources");
+/// assert_eq!(redirect(&from_env(), "/opt/discord/resources/app.asar").as_deref(), Some("/tmp/mod.asar"));
+/// assert_eq!(redirect(&from_env(), "/opt/slack/resources/app.asar"), None);
+///
+/// std::env::remove_var("MODLOADER_RESOURCES_DIR");
+/// std::env::set_var("APPDIR", "/tmp/.mount_DiscorXyZ");
+/// assert_eq!(redirect(&from_env(), "/tmp/.mount_DiscorXyZ/resources/app.asar").as_deref(), Some("/tmp/mod.asar"));
+/// assert_eq!(redirect(&from_env(), "/tmp/.mount_DiscorXyZ/usr/lib/discord/resources/app.asar").as_deref(), Some("/tmp/mod.asar"));
+/// assert_eq!(redirect(&from_env(), "/tmp/.mount_DiscorXyZ/other-resources/app.asar"), None);
+/// assert_eq!(redirect(&from_env(), "/opt/discord/resources/app.asar"), None);
+/// std::env::remove_var("APPDIR");
+///
+/// // Malformed rules are ignored rather than half applied.
+/// std::env::set_var("MODLOADER_REDIRECTS", "[{\"match\": \"regex\"}]");
+/// assert_eq!(from_env().len(), 2);
+/// ```
+pub fn from_env() -> Vec<Rule> {
+    from_vars(|name| std::env::var(name).ok())
+}
+
+/// Like [from_env], reading the variables with `var`, e.g. through libc in the hooks.
+pub(crate) fn from_vars(var: impl Fn(&str) -> Option<String>) -> Vec<Rule> {
+    let mut rules = var("MODLOADER_REDIRECTS")
+        .and_then(|json| from_json(&json))
+        .unwrap_or_default();
+
+    let Some(asar_path) = var("MODLOADER_ASAR_PATH") else {
+        return rules;
+    };
+
+    let resources_dir = match var("MODLOADER_RESOURCES_DIR") {
+        Some(resources_dir) => glob_escape(&normalize(&resources_dir)).into_owned(),
+        // The resources directory can be anywhere in the AppImage, e.g. `usr/lib/<app>/resources`.
+        None => match var("APPDIR") {
+            Some(app_dir) => {
+                let app_dir = glob_escape(&normalize(&app_dir)).into_owned();
+                rules.extend(default_rules(&asar_path, &format!("{app_dir}/resources")));
+
+                format!("{app_dir}/*/resources")
+            }
+            None => ANY_RESOURCES_DIR.to_string(),
+        },
+    };
+
+    rules.extend(default_rules(&asar_path, &resources_dir));
+
+    rules
+}
+
+/// Find where a C path is redirected to, as the hooks do.
+///
+/// Paths that aren't valid UTF-8 can't match a rule, so they are never redirected.
+///
+/// ```rust
+/// use electron_hook::redirect::{default_rules, redirect_c_path};
+///
+/// let rules = default_rules("/tmp/mod.asar", "/opt/discord/resources");
+///
+/// assert_eq!(
+///     redirect_c_path(&rules, c"/opt/discord/resources/app.asar").as_deref(),
+///     Some(c"/tmp/mod.asar"),
+/// );
+/// assert_eq!(redirect_c_path(&rules, c"/opt/discord/resources/\xff/app.asar"), None);
+/// assert_eq!(redirect_c_path(&[], c"/opt/discord/resources/app.asar"), None);
+/// ```
+pub fn redirect_c_path(rules: &[Rule], path: &std::ffi::CStr) -> Option<std::ffi::CString> {
+    let redirect_to = redirect(rules, path.to_str().ok()?)?;
+
+    std::ffi::CString::new(redirect_to).ok()
+}
+
+/// Serialize rules for `MODLOADER_REDIRECTS`.
+///
+/// ```rust
+/// use electron_hook::redirect::{from_json, to_json, Rule};
+///
+/// let rules = vec![
+///     Rule::prefix("/opt/discord/resources/icons", "/home/CoolPerson/icons"),
+///     Rule::exact("app-update.yml", "/dev/null").with_scope("/opt/discord/resources"),
+/// ];
+///
+/// assert_eq!(from_json(&to_json(&rules)), Some(rules));
+/// ```
+pub fn to_json(rules: &[Rule]) -> String {
+    serde_json::Value::Array(rules.iter().map(Rule::to_json).collect()).to_string()
+}
+
+/// Parse rules from `MODLOADER_REDIRECTS`, returning `None` if any of them is malformed.
+pub fn from_json(json: &str) -> Option<Vec<Rule>> {
+    serde_json::from_str::<serde_json::Value>(json)
+        .ok()?
+        .as_array()?
+        .iter()
+        .map(Rule::from_json)
+        .collect()
+}
+
+/// Match `text` against a glob `pattern`, where `*` also matches `/`.
+///
+/// ```rust
+/// use electron_hook::redirect::glob_match;
+///
+/// assert!(glob_match("*/resources/*.pak", "/opt/discord/resources/en-US.pak"));
+/// assert!(glob_match("/opt/app-?/*", "/opt/app-1/resources/app.asar"));
+/// assert!(!glob_match("*/resources/app.asar", "/opt/discord/resources/app.asar.unpacked"));
+/// assert!(glob_match("/opt/\\*/*", "/opt/*/resources"));
+/// assert!(!glob_match("/opt/\\*/*", "/opt/discord/resources"));
+/// ```
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let text = text.as_bytes();
+
+    let (mut p, mut t) = (0, 0);
+
+    // Where to resume after the last `*`, if the rest doesn't match.
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(b'\\') if pattern.get(p + 1) == Some(&text[t]) => {
+                p += 2;
+                t += 1;
+            }
+            Some(&c) if c != b'\\' && (c == b'?' || c == text[t]) => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, star_t)) => {
+                    p = star + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Escape `text` so [glob_match] only matches it literally, e.g. to use a directory in a pattern.
+///
+/// ```rust
+/// use electron_hook::redirect::{glob_escape, glob_match};
+///
+/// let dir = glob_escape("/home/CoolPerson/Apps [old]/Discord*/resources");
+///
+/// assert!(glob_match(&dir, "/home/CoolPerson/Apps [old]/Discord*/resources"));
+/// assert!(!glob_match(&dir, "/home/CoolPerson/Apps [old]/Discord-canary/resources"));
+/// ```
+pub fn glob_escape(text: &str) -> std::borrow::Cow<'_, str> {
+    if !text.contains(['*', '?', '[', '\\']) {
+        return text.into();
+    }
+
+    let mut escaped = String::with_capacity(text.len() + 4);
+
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | '\\') {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped.into()
+}
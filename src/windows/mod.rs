@@ -8,53 +8,77 @@ use winapi::um::{
     winuser::{MessageBoxA, MB_ICONERROR},
 };
 
-pub fn launch(
-    executable: &str,
-    library_path: &str,
-    asar_path: &str,
-    args: Vec<String>,
-) -> Result<Option<u32>, String> {
+use super::{Error, Instance, Launcher, Result};
+
+pub fn launch(launcher: &Launcher, executable: &std::path::Path) -> Result<Instance> {
+    let library_path = launcher.library_path.as_str();
+    let asar_path = launcher.asar_path.as_str();
+
     unsafe {
         let process_args = std::env::args().skip(1).collect::<Vec<String>>();
         let process_args_json =
             serde_json::to_string(&process_args).unwrap_or_else(|_| "[]".into());
 
-        let executable = std::path::Path::new(executable);
+        if !executable.is_file() {
+            return Err(Error::ExecutableNotFound(executable.into()));
+        }
+
+        let invalid_path = |path: &std::path::Path| Error::InvalidPath(path.into());
 
         let working_dir = executable
             .parent()
-            .ok_or("Failed to get executable directory".to_string())?;
+            .ok_or_else(|| invalid_path(executable))?;
 
         let shared_object = std::ffi::CString::new(library_path)
-            .map_err(|_| "Failed to convert shared object path to CString")?;
+            .map_err(|_| invalid_path(library_path.as_ref()))?;
 
         let folder_name = working_dir
             .file_name()
             .and_then(|name| name.to_str())
-            .ok_or("Failed to get directory name as string")?;
+            .ok_or_else(|| invalid_path(working_dir))?;
 
         // Set env vars needed for the child processes
         std::env::set_var("MODLOADER_ASAR_PATH", asar_path);
-        std::env::set_var("MODLOADER_EXECUTABLE", std::env::current_exe().unwrap());
+        std::env::set_var(
+            "MODLOADER_EXECUTABLE",
+            std::env::current_exe().map_err(Error::CurrentExe)?,
+        );
         std::env::set_var("MODLOADER_LIBRARY_PATH", library_path);
         std::env::set_var("MODLOADER_FOLDER_NAME", folder_name);
         std::env::set_var("MODLOADER_ORIGINAL_ASAR_RELATIVE", "../_app.asar");
         std::env::set_var("MODLOADER_PROCESS_ARGV", process_args_json);
 
-        let working_dir = std::ffi::CString::new(working_dir.parent().unwrap().to_str().unwrap())
-            .map_err(|_| "Failed to convert directory path to CString")?;
+        if let Some(profile_dir) = &launcher.profile_dir {
+            std::env::set_var("MODLOADER_PROFILE_DIR", profile_dir);
+        }
+
+        for (key, value) in &launcher.env {
+            std::env::set_var(key, value);
+        }
+
+        let working_dir = match &launcher.working_dir {
+            Some(working_dir) => working_dir.as_path(),
+            None => working_dir
+                .parent()
+                .ok_or_else(|| invalid_path(working_dir))?,
+        };
+
+        let working_dir = working_dir
+            .to_str()
+            .and_then(|dir| std::ffi::CString::new(dir).ok())
+            .ok_or_else(|| invalid_path(working_dir))?;
 
         let executable_path = executable
             .to_str()
-            .ok_or("Failed to convert executable path to string")?;
+            .ok_or_else(|| invalid_path(executable))?;
 
-        let executable = std::ffi::CString::new(executable_path)
-            .map_err(|_| "Failed to convert executable path to CString")?;
+        let executable =
+            std::ffi::CString::new(executable_path).map_err(|_| invalid_path(executable))?;
 
-        let args = args.join(" ");
+        let args = launcher.args.join(" ");
         let command_line = format!("\"{executable_path}\" {args}");
         let command_line = std::ffi::CString::new(command_line)
-            .map_err(|_| "Failed to convert command line to CString")?;
+            .map_err(|e| Error::Spawn(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)))?;
 
         let mut startup_info: _STARTUPINFOA = std::mem::zeroed();
         let mut process_info: _PROCESS_INFORMATION = std::mem::zeroed();
@@ -80,14 +104,17 @@ pub fn launch(
                 "Error Hooking".as_ptr() as *const i8,
                 MB_ICONERROR,
             );
-            return Err("Failed to hook CreateProcessW. Please report this issue.".into());
+            return Err(Error::Spawn(std::io::Error::last_os_error()));
         }
 
         ResumeThread(process_info.hThread as _);
 
         CloseHandle(process_info.hThread as _);
-        CloseHandle(process_info.hProcess as _);
-    }
 
-    Ok(None)
+        // The process handle is closed when the Instance is dropped.
+        Ok(Instance::from_handle(
+            process_info.hProcess as _,
+            process_info.dwProcessId,
+        ))
+    }
 }